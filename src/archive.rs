@@ -0,0 +1,169 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::Path,
+};
+
+use flate2::{write::GzEncoder, Compression};
+use tokio::sync::mpsc;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::error::Error;
+
+/// The number of entries that may be waiting to be written to the archive
+const PENDING_ENTRIES: usize = 64;
+
+/// The formats of archives bulk output can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format of the archive at `path`, based on its extension,
+    /// or `None` if `path` doesn't look like an archive.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy();
+        [
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+            (".zip", Self::Zip),
+        ]
+        .into_iter()
+        .find_map(|(extension, format)| name.ends_with(extension).then_some(format))
+    }
+}
+
+/// Writes files into an archive on a blocking thread, as they're added
+pub struct ArchiveWriter {
+    sender: mpsc::Sender<(String, Vec<u8>)>,
+    writer: tokio::task::JoinHandle<Result<(), Error>>,
+}
+
+impl ArchiveWriter {
+    /// Create a new archive at `path`, only overwriting an existing file if `force` is set
+    pub fn create(path: &Path, format: ArchiveFormat, force: bool) -> Result<Self, Error> {
+        let file = if force {
+            File::create(path)?
+        } else {
+            File::create_new(path)?
+        };
+        let (sender, mut receiver) = mpsc::channel(PENDING_ENTRIES);
+        let writer = tokio::task::spawn_blocking(move || {
+            let file = BufWriter::new(file);
+            let mut file = match format {
+                ArchiveFormat::Tar => write_tar(file, &mut receiver)?,
+                ArchiveFormat::TarGz => {
+                    let encoder = GzEncoder::new(file, Compression::default());
+                    write_tar(encoder, &mut receiver)?.finish()?
+                }
+                ArchiveFormat::TarZst => {
+                    let encoder = zstd::Encoder::new(file, 0)?;
+                    write_tar(encoder, &mut receiver)?.finish()?
+                }
+                ArchiveFormat::Zip => write_zip(file, &mut receiver)?,
+            };
+            file.flush()?;
+            Ok(())
+        });
+        Ok(Self { sender, writer })
+    }
+
+    /// Add a file called `name` to the archive
+    pub async fn add(&self, name: String, contents: Vec<u8>) -> Result<(), Error> {
+        self.sender
+            .send((name, contents))
+            .await
+            .map_err(|_| anyhow::anyhow!("The archive writer stopped, as writing failed"))?;
+        Ok(())
+    }
+
+    /// Write the remaining files, and finish the archive
+    pub async fn finish(self) -> Result<(), Error> {
+        drop(self.sender);
+        self.writer.await.unwrap()
+    }
+}
+
+/// Write the files received from `receiver` to a tar archive written to `out`
+fn write_tar<W: Write>(
+    out: W,
+    receiver: &mut mpsc::Receiver<(String, Vec<u8>)>,
+) -> Result<W, Error> {
+    let mut builder = tar::Builder::new(out);
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    while let Some((name, contents)) = receiver.blocking_recv() {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, name, contents.as_slice())?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+/// Write the files received from `receiver` to a zip archive written to `out`
+fn write_zip<W: Write + Seek>(
+    out: W,
+    receiver: &mut mpsc::Receiver<(String, Vec<u8>)>,
+) -> Result<W, Error> {
+    let mut zip = ZipWriter::new(out);
+    // PNGs are compressed already
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    while let Some((name, contents)) = receiver.blocking_recv() {
+        zip.start_file(name, options).map_err(anyhow::Error::from)?;
+        zip.write_all(&contents)?;
+    }
+    Ok(zip.finish().map_err(anyhow::Error::from)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use test_case::case;
+
+    use super::{ArchiveFormat, ArchiveWriter};
+
+    #[case("out.tar" => Some(ArchiveFormat::Tar))]
+    #[case("out.tar.gz" => Some(ArchiveFormat::TarGz))]
+    #[case("out.tar.zst" => Some(ArchiveFormat::TarZst))]
+    #[case("out.zip" => Some(ArchiveFormat::Zip))]
+    #[case("out" => None)]
+    #[case("out.png" => None)]
+    fn test_detect_format(path: &str) -> Option<ArchiveFormat> {
+        ArchiveFormat::detect(Path::new(path))
+    }
+
+    #[tokio::test]
+    async fn test_write_tar_zst() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.tar.zst");
+        let writer = ArchiveWriter::create(&path, ArchiveFormat::TarZst, false).unwrap();
+        writer.add("a.png".into(), b"a".to_vec()).await.unwrap();
+        writer.add("b.png".into(), b"bb".to_vec()).await.unwrap();
+        writer.finish().await.unwrap();
+        assert!(ArchiveWriter::create(&path, ArchiveFormat::TarZst, false).is_err());
+
+        let decoder = zstd::Decoder::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut archive = tar::Archive::new(decoder);
+        let entries: Vec<_> = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (e.path().unwrap().display().to_string(), e.size())
+            })
+            .collect();
+        assert_eq!(entries, [("a.png".into(), 1), ("b.png".into(), 2)]);
+    }
+}
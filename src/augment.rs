@@ -1,235 +1,1901 @@
 use std::{
-    cell::RefCell,
-    collections::{HashMap, HashSet},
-    path::Path,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
 };
 
-use db_dump::{crate_owners::OwnerId, crates::CrateId, teams::TeamId, users::UserId};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc, Weekday};
+use db_dump::{
+    categories::CategoryId, crate_owners::OwnerId, crates::CrateId, keywords::KeywordId,
+    teams::TeamId, users::UserId, versions::VersionId,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    convert::{CrateData, TeamCrateOwner, UserCrateOwner},
+    convert::{CrateCategory, CrateData, TeamCrateOwner, UserCrateOwner},
+    crates_io,
+    dump::{CrateOwnerRow, CrateRow, DbDump, DependencyRow, VersionRow, LOAD_PROGRESS},
     error::Error,
     spec::CrateName,
+    supplement::SupplementaryCrate,
 };
 
-#[derive(Debug, Hash)]
+#[derive(Debug, Hash, Serialize, Deserialize)]
 struct DbDumpCrateData {
-    description: String,
-    owners: Vec<OwnerId>,
+    description: Box<str>,
+    user_owners: Vec<UserId>,
+    team_owners: Vec<TeamId>,
+    /// Download totals of the last [`SPARKLINE_WEEKS`] full weeks, oldest first
+    weekly_downloads: Box<[u64]>,
+    /// All published versions, including yanked ones
+    versions: Vec<DbDumpVersionData>,
+    /// The default version, as an index into `versions`
+    default_version: Option<u32>,
+    /// All-time download count
+    downloads: u64,
+    /// Download count of the last 90 days
+    recent_downloads: u64,
+    /// The number of crates whose default version depends on this crate
+    dependents: u64,
+    keywords: Vec<KeywordId>,
+    categories: Vec<CategoryId>,
+    repository: Option<Box<str>>,
+    homepage: Option<Box<str>>,
+    documentation: Option<Box<str>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl DbDumpCrateData {
+    /// The default version, or if that was yanked, the most
+    /// recently published version that wasn't yanked, if any
+    fn current_version(&self) -> Option<&DbDumpVersionData> {
+        let default = self
+            .default_version
+            .and_then(|idx| self.versions.get(idx as usize))?;
+        if !default.yanked {
+            return Some(default);
+        }
+        self.versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .max_by_key(|v| v.created_at)
+            .or(Some(default))
+    }
 }
 
-#[derive(Debug, Hash)]
+#[derive(Debug, Hash, Serialize, Deserialize)]
+struct DbDumpVersionData {
+    /// The version number, e.g. `1.0.219`
+    num: Box<str>,
+    /// The license, as an index into [`CrateDb::licenses`]
+    license: Option<u32>,
+    /// The minimum supported Rust version
+    rust_version: Option<Box<str>>,
+    /// The size of the `.crate` file in bytes
+    crate_size: Option<u64>,
+    /// When the version was published
+    created_at: DateTime<Utc>,
+    yanked: bool,
+}
+
+#[derive(Debug, Hash, Serialize, Deserialize)]
+struct DbDumpCategoryData {
+    /// The display name, e.g. `Web programming::HTTP server`
+    name: Box<str>,
+    /// The slug, e.g. `web-programming::http-server`
+    slug: Box<str>,
+}
+
+/// The number of weeks of download history to keep per crate.
+/// The db dump only contains the downloads of the last 90 days,
+/// so this should be no more than 12.
+const SPARKLINE_WEEKS: usize = 12;
+
+#[derive(Debug, Hash, Serialize, Deserialize)]
 struct DbDumpCrateOwnerData {
-    avatar: String,
+    avatar: Box<str>,
+    /// The GitHub login of users, or the `org/team` name of teams
+    login: Box<str>,
+    /// The display name of users, if they set one
+    name: Option<Box<str>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CrateDb {
-    crates: HashMap<CrateId, DbDumpCrateData>,
-    crate_names: HashMap<String, CrateId>,
-    user_crate_owners: HashMap<UserId, Option<DbDumpCrateOwnerData>>,
-    team_crate_owners: HashMap<TeamId, Option<DbDumpCrateOwnerData>>,
+    /// Crate data, indexed by the values of `crate_names`
+    crates: Vec<DbDumpCrateData>,
+    crate_names: FxHashMap<Box<str>, u32>,
+    /// Crate names by their [normalized](normalize_crate_name) form
+    normalized_names: FxHashMap<Box<str>, Box<str>>,
+    /// Deduplicated licenses. Most crates share one of a handful of licenses
+    licenses: Vec<Box<str>>,
+    user_crate_owners: FxHashMap<UserId, DbDumpCrateOwnerData>,
+    team_crate_owners: FxHashMap<TeamId, DbDumpCrateOwnerData>,
+    keywords: FxHashMap<KeywordId, Box<str>>,
+    categories: FxHashMap<CategoryId, DbDumpCategoryData>,
+    /// When the database dump was exported, if known
+    dump_timestamp: Option<DateTime<Utc>>,
+}
+
+/// Deduplicates strings, handing out indices into the list of unique strings
+#[derive(Debug, Default)]
+struct Interner {
+    strings: Vec<Box<str>>,
+    indices: FxHashMap<Box<str>, u32>,
+}
+
+impl Interner {
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(idx) = self.indices.get(s) {
+            return *idx;
+        }
+        let idx = self.strings.len() as u32;
+        self.strings.push(s.into());
+        self.indices.insert(s.into(), idx);
+        idx
+    }
+
+    fn into_strings(self) -> Vec<Box<str>> {
+        self.strings
+    }
 }
 
 pub enum LoadFilter {
-    Select(HashSet<String>),
+    /// Crates by their [normalized](normalize_crate_name) name, with the names as listed.
+    /// Use [`LoadFilter::select`] to create one.
+    Select(HashMap<String, String>),
     Single(String),
+    /// Crates whose name starts with the string
+    Prefix(String),
+    /// Crates whose name matches the glob pattern, e.g. `tokio-*`
+    Glob(glob::Pattern),
+    /// Crates whose name matches the regular expression
+    Regex(regex::Regex),
     All,
 }
 
 impl LoadFilter {
-    fn matches(&self, name: &str) -> bool {
+    /// Create a filter for the listed crates, however their names are capitalized or spelled
+    pub fn select(names: impl IntoIterator<Item = String>) -> Self {
+        LoadFilter::Select(
+            names
+                .into_iter()
+                .map(|name| (normalize_crate_name(&name), name))
+                .collect(),
+        )
+    }
+
+    /// Create a filter for crates matching a glob pattern. Patterns
+    /// that only end in a `*`, e.g. `tokio-*`, become a [`LoadFilter::Prefix`].
+    pub fn from_pattern(pattern: &str) -> Result<Self, glob::PatternError> {
+        if let Some(prefix) = pattern.strip_suffix('*') {
+            if !prefix.contains(['*', '?', '[']) {
+                return Ok(LoadFilter::Prefix(prefix.to_string()));
+            }
+        }
+        glob::Pattern::new(pattern).map(LoadFilter::Glob)
+    }
+
+    pub(crate) fn matches(&self, name: &str) -> bool {
         match self {
             LoadFilter::All => true,
-            LoadFilter::Single(item) => item == name,
-            LoadFilter::Select(items) => items.contains(name),
+            LoadFilter::Single(item) => crate_names_match(item, name),
+            LoadFilter::Select(items) => items.contains_key(&normalize_crate_name(name)),
+            LoadFilter::Prefix(prefix) => name.starts_with(prefix),
+            LoadFilter::Glob(pattern) => pattern.matches(name),
+            LoadFilter::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// The number of crates expected to match this filter
+    fn expected_crates(&self) -> usize {
+        match self {
+            LoadFilter::All => EXPECTED_CRATES,
+            LoadFilter::Single(_) => 1,
+            LoadFilter::Select(items) => items.len(),
+            // Patterns usually select a family of related crates
+            LoadFilter::Prefix(_) | LoadFilter::Glob(_) | LoadFilter::Regex(_) => {
+                EXPECTED_PATTERN_CRATES
+            }
+        }
+    }
+}
+
+/// Filters on the data of crates rather than their names. Crates need to
+/// match at least one of the categories and one of the keywords, if any,
+/// and have at least `min_downloads` all-time downloads.
+#[derive(Debug, Default, Clone)]
+pub struct DataFilter {
+    /// Category slugs, which also match their subcategories
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>,
+    pub min_downloads: u64,
+}
+
+impl DataFilter {
+    pub fn is_empty(&self) -> bool {
+        self.categories.is_empty() && self.keywords.is_empty() && self.min_downloads == 0
+    }
+
+    /// Whether the augmented crate data matches this filter
+    pub fn matches(&self, data: &CrateData) -> bool {
+        self.matches_parts(
+            data.categories.iter().map(|c| c.slug.as_str()),
+            data.keywords.iter().map(String::as_str),
+            data.downloads,
+        )
+    }
+
+    fn matches_parts<'a>(
+        &self,
+        mut categories: impl Iterator<Item = &'a str>,
+        mut keywords: impl Iterator<Item = &'a str>,
+        downloads: u64,
+    ) -> bool {
+        if downloads < self.min_downloads {
+            return false;
+        }
+        let category_matches = |slug: &str| {
+            self.categories.iter().any(|c| {
+                slug.strip_prefix(c.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+        };
+        (self.categories.is_empty() || categories.any(category_matches))
+            && (self.keywords.is_empty() || keywords.any(|k| self.keywords.iter().any(|f| f == k)))
+    }
+}
+
+/// Roughly the number of crates on crates.io, used to pre-size maps
+/// when loading every crate
+const EXPECTED_CRATES: usize = 200_000;
+
+/// The number of crates expected to match a pattern, used to pre-size maps
+const EXPECTED_PATTERN_CRATES: usize = 1_000;
+
+/// Roughly the average number of versions per crate on crates.io
+const AVERAGE_VERSIONS_PER_CRATE: usize = 10;
+
+/// The number of tables loaded by [`CrateDb::load_from_dump`] if nothing is skipped
+const LOADED_TABLES: usize = 13;
+
+/// The maximum number of keywords listed per crate. This is
+/// also the maximum number of keywords crates.io allows.
+pub(crate) const MAX_KEYWORDS: usize = 5;
+
+/// Whether to fall back to the crates.io API for crates missing from the dump
+static API_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`enable_api_fallback`] was called
+pub(crate) fn api_fallback_enabled() -> bool {
+    API_FALLBACK.load(Ordering::Relaxed)
+}
+
+/// Fetch crates that are missing from the database dump from the crates.io
+/// API instead. Newly published crates can be missing from the dump for a day.
+pub fn enable_api_fallback() {
+    API_FALLBACK.store(true, Ordering::Relaxed);
+}
+
+/// Whether to print load statistics after loading a dump
+static LOAD_STATS: AtomicBool = AtomicBool::new(false);
+
+/// Print the time taken and number of rows loaded for
+/// each table after loading a database dump.
+pub fn enable_load_stats() {
+    LOAD_STATS.store(true, Ordering::Relaxed);
+}
+
+/// Which optional data to load from the database dump. Skipping data that
+/// isn't used by the template saves both memory and loading time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadOptions {
+    keywords: bool,
+    categories: bool,
+    dependents: bool,
+    download_history: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            keywords: true,
+            categories: true,
+            dependents: true,
+            download_history: true,
         }
     }
 }
 
+impl LoadOptions {
+    /// Whether to load the crates' keywords
+    pub fn keywords(mut self, load: bool) -> Self {
+        self.keywords = load;
+        self
+    }
+
+    /// Whether to load the crates' categories
+    pub fn categories(mut self, load: bool) -> Self {
+        self.categories = load;
+        self
+    }
+
+    /// Whether to count the crates' dependents. This requires
+    /// loading the `dependencies` table, which is the largest one.
+    pub fn dependents(mut self, load: bool) -> Self {
+        self.dependents = load;
+        self
+    }
+
+    /// Whether to load the daily downloads of the last 90 days, used for
+    /// the weekly downloads and the recent download count
+    pub fn download_history(mut self, load: bool) -> Self {
+        self.download_history = load;
+        self
+    }
+
+    /// Whether all optional data is loaded
+    pub fn is_all(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The number of tables that are loaded with these options
+    fn tables(&self) -> usize {
+        LOADED_TABLES
+            - 2 * usize::from(!self.keywords)
+            - 2 * usize::from(!self.categories)
+            - usize::from(!self.dependents)
+            - usize::from(!self.download_history)
+    }
+}
+
+/// Loads a [`CrateDb`] from a database dump, with the crates and data to
+/// load, and the number of threads to load them with, configured up front.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), og_loc::error::Error> {
+/// use og_loc::augment::{CrateDb, LoadFilter, LoadOptions};
+///
+/// let db = CrateDb::builder("./db-dump.tar.gz")
+///     .filter(LoadFilter::Single("serde".into()))
+///     .options(LoadOptions::default().dependents(false))
+///     .threads(4)
+///     .load()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CrateDbBuilder {
+    dump_path: PathBuf,
+    filter: LoadFilter,
+    options: LoadOptions,
+    threads: Option<usize>,
+}
+
+impl CrateDbBuilder {
+    /// Only load the crates matching `filter`. Loads all crates by default.
+    pub fn filter(mut self, filter: LoadFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Which optional data to load. Loads everything by default.
+    pub fn options(mut self, options: LoadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The number of threads to parse the dump's tables with. Uses
+    /// the global rayon thread pool, with a thread per CPU, by default.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Load the db on a blocking task
+    pub async fn load(self) -> Result<CrateDb, Error> {
+        tokio::task::spawn_blocking(move || self.load_blocking())
+            .await
+            .unwrap()
+    }
+
+    /// Load the db on the current thread, which blocks until it's done
+    pub fn load_blocking(self) -> Result<CrateDb, Error> {
+        let Self {
+            dump_path,
+            filter,
+            options,
+            threads,
+        } = self;
+        let mut dump = DbDump::open(&dump_path);
+        if let Some(threads) = threads {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|i| format!("og-loc-load-{i}"))
+                .build()
+                .map_err(anyhow::Error::from)?;
+            dump = dump.with_thread_pool(Arc::new(pool));
+        }
+
+        let Some(cache_dir) = CACHE_DIR.get() else {
+            return CrateDb::load_from_dump(&dump, &filter, options);
+        };
+        let cache_path = cache_dir.join(format!("crate-db-{:016x}.bin", cache_key(&dump)?));
+        match CrateDb::read_cache(&cache_path) {
+            Ok(mut db) => {
+                println!(
+                    "🗃️ Loaded crate data from cache at {}",
+                    cache_path.display()
+                );
+                db.retain(&filter);
+                db.drop_skipped(options);
+                return Ok(db);
+            }
+            Err(e) if cache_path.exists() => {
+                eprintln!("Ignoring unreadable cache at {}: {e}", cache_path.display());
+            }
+            Err(_) => {}
+        }
+
+        let db = CrateDb::load_from_dump(&dump, &filter, options)?;
+        // Caches are only useful if they contain every crate
+        if matches!(filter, LoadFilter::All) && options.is_all() {
+            if let Err(e) = db.write_cache(&cache_path) {
+                eprintln!("Error writing cache to {}: {e}", cache_path.display());
+            }
+        }
+        Ok(db)
+    }
+}
+
+/// The crates that were added, changed or removed between two [`CrateDb`]s
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CrateDbDiff {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl CrateDbDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// The directory in which fully loaded [`CrateDb`]s are cached
+static CACHE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Enable caching the parsed database dump in `path`. Caches are
+/// written after loading a full dump, and used by subsequent loads
+/// of the same dump, regardless of their filter. Can only be set once.
+pub fn set_cache_dir(path: impl Into<PathBuf>) -> Result<(), Error> {
+    CACHE_DIR
+        .set(path.into())
+        .map_err(|_| anyhow::anyhow!("Cache directory was already set"))?;
+    Ok(())
+}
+
 impl CrateDb {
-    fn load_with_filter_blocking(
-        dump_path: impl AsRef<Path>,
-        filter: LoadFilter,
+    fn read_cache(path: &Path) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(path)?);
+        bincode::deserialize_from(reader).map_err(|e| anyhow::anyhow!(e).into())
+    }
+
+    /// Write this db to `path`, replacing any previously cached db.
+    fn write_cache(&self, path: &Path) -> Result<(), Error> {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(dir)?;
+        // Caches of older dumps will never be used again
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with("crate-db-") && name.ends_with(".bin") {
+                std::fs::remove_file(entry.path())?;
+            }
+        }
+
+        let part_path = path.with_extension("bin.part");
+        let mut writer = BufWriter::new(File::create(&part_path)?);
+        bincode::serialize_into(&mut writer, self).map_err(|e| anyhow::anyhow!(e))?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        std::fs::rename(part_path, path)?;
+        Ok(())
+    }
+
+    /// Drop all crates that don't match `filter`
+    fn retain(&mut self, filter: &LoadFilter) {
+        self.retain_by(|name, _| filter.matches(name));
+    }
+
+    /// Drop all crates whose data doesn't match `filter`
+    pub fn retain_data(&mut self, filter: &DataFilter) {
+        if filter.is_empty() {
+            return;
+        }
+        let keep: Vec<bool> = self
+            .crates
+            .iter()
+            .map(|c| {
+                filter.matches_parts(
+                    c.categories
+                        .iter()
+                        .filter_map(|id| self.categories.get(id))
+                        .map(|category| &*category.slug),
+                    c.keywords
+                        .iter()
+                        .filter_map(|id| self.keywords.get(id))
+                        .map(|keyword| &**keyword),
+                    c.downloads,
+                )
+            })
+            .collect();
+        self.retain_by(|_, idx| keep[idx]);
+    }
+
+    /// Drop the data that `options` skips loading, e.g. after loading from a cache
+    fn drop_skipped(&mut self, options: LoadOptions) {
+        for c in &mut self.crates {
+            if !options.keywords {
+                c.keywords = vec![];
+            }
+            if !options.categories {
+                c.categories = vec![];
+            }
+            if !options.dependents {
+                c.dependents = 0;
+            }
+            if !options.download_history {
+                c.weekly_downloads = Box::default();
+                c.recent_downloads = 0;
+            }
+        }
+        if !options.keywords {
+            self.keywords = FxHashMap::default();
+        }
+        if !options.categories {
+            self.categories = FxHashMap::default();
+        }
+    }
+
+    /// Drop all crates for which `f`, given the crate's name and
+    /// its index into [`Self::crates`], returns `false`.
+    fn retain_by(&mut self, mut f: impl FnMut(&str, usize) -> bool) {
+        let mut crates: Vec<_> = std::mem::take(&mut self.crates)
+            .into_iter()
+            .map(Some)
+            .collect();
+        // Names pointing at missing crates are dropped as well
+        self.crate_names.retain(|name, idx| {
+            if *idx as usize >= crates.len() || !f(name, *idx as usize) {
+                return false;
+            }
+            let Some(data) = crates.get_mut(*idx as usize).and_then(Option::take) else {
+                return false;
+            };
+            self.crates.push(data);
+            *idx = self.crates.len() as u32 - 1;
+            true
+        });
+        self.crates.shrink_to_fit();
+        self.crate_names.shrink_to_fit();
+        self.normalized_names
+            .retain(|_, name| self.crate_names.contains_key(name));
+        self.normalized_names.shrink_to_fit();
+
+        let user_ids: FxHashSet<_> = self.crates.iter().flat_map(|c| &c.user_owners).collect();
+        self.user_crate_owners.retain(|id, _| user_ids.contains(id));
+        let team_ids: FxHashSet<_> = self.crates.iter().flat_map(|c| &c.team_owners).collect();
+        self.team_crate_owners.retain(|id, _| team_ids.contains(id));
+        let keyword_ids: FxHashSet<_> = self.crates.iter().flat_map(|c| &c.keywords).collect();
+        self.keywords.retain(|id, _| keyword_ids.contains(id));
+        let category_ids: FxHashSet<_> = self.crates.iter().flat_map(|c| &c.categories).collect();
+        self.categories.retain(|id, _| category_ids.contains(id));
+    }
+
+    fn load_from_dump(
+        dump: &DbDump,
+        filter: &LoadFilter,
+        options: LoadOptions,
     ) -> Result<Self, Error> {
-        let crates = RefCell::new(HashMap::new());
-        let crate_owners = RefCell::new(HashMap::new());
+        let expected_crates = filter.expected_crates();
+        let crates = RefCell::new(FxHashMap::with_capacity_and_hasher(
+            expected_crates,
+            Default::default(),
+        ));
+        let crate_owners = RefCell::new(FxHashMap::default());
+        let keywords = RefCell::new(FxHashMap::default());
+        let categories = RefCell::new(FxHashMap::default());
+        let licenses = RefCell::new(Interner::default());
 
-        let mut crate_names = HashMap::new();
+        let mut crate_names =
+            FxHashMap::with_capacity_and_hasher(expected_crates, Default::default());
+        let dump_timestamp;
         {
-            // Sadly, the order in which the CSVs are loaded is non-deterministic,
-            // but in order to save memory, we'll only want to load data that
-            // matches the filter. Luckily, `db_dump::Loader` will skip
-            // CSVs that are not requested, and thus won't iterate over
-            // a CSV more than once, but the archive does need to be inflated
+            // In order to save memory, we'll only want to load data that
+            // matches the filter, so the tables are loaded one by one.
+            // For archives, this means the archive needs to be inflated
             // multiple times.
 
-            let mut loader = db_dump::Loader::new();
-            loader.crates(|c| {
+            dump_timestamp = match dump.metadata() {
+                Ok(metadata) => Some(metadata.timestamp),
+                Err(e) => {
+                    eprintln!("Could not read the database dump's metadata: {e}");
+                    None
+                }
+            };
+            LOAD_PROGRESS.start(options.tables());
+            dump.load("crates", |c: CrateRow| {
                 if !filter.matches(&c.name) {
                     return;
                 }
-                // Cut off description if it's too long.
-                // Sadly typst doesn't seem to provide a nice
-                // way to do this.
-                let mut description = c.description;
-                if let Some((idx, _)) = description.char_indices().nth(110) {
-                    let idx = description[..idx]
-                        .rfind([' ', ',', '.', ';', '!', '?'])
-                        .unwrap_or(idx);
-                    description.truncate(idx);
-                    description.push('…');
-                };
-                let description = description.trim().replace(|c: char| c.is_whitespace(), " ");
-
                 let data = DbDumpCrateData {
-                    description,
-                    owners: vec![],
+                    description: truncate_description(c.description).into(),
+                    user_owners: vec![],
+                    team_owners: vec![],
+                    weekly_downloads: Box::default(),
+                    versions: vec![],
+                    default_version: None,
+                    downloads: 0,
+                    recent_downloads: 0,
+                    dependents: 0,
+                    keywords: vec![],
+                    categories: vec![],
+                    repository: c.repository.map(Into::into),
+                    homepage: c.homepage.map(Into::into),
+                    documentation: c.documentation.map(Into::into),
+                    created_at: c.created_at,
+                    updated_at: c.updated_at,
                 };
                 crates.borrow_mut().insert(c.id, data);
                 crate_names.insert(c.name, c.id);
-            });
-            loader.load(&dump_path)?;
+            })?;
 
-            let mut loader = db_dump::Loader::new();
-            loader.crate_owners(|co| {
+            dump.load("crate_owners", |co: CrateOwnerRow| {
+                let Some(owner_id) = co.owner_id() else {
+                    return;
+                };
                 crates.borrow_mut().entry(co.crate_id).and_modify(|c| {
-                    crate_owners.borrow_mut().insert(co.owner_id, None);
-                    c.owners.push(co.owner_id);
+                    crate_owners.borrow_mut().insert(owner_id, None);
+                    match owner_id {
+                        OwnerId::User(uid) => c.user_owners.push(uid),
+                        OwnerId::Team(tid) => c.team_owners.push(tid),
+                    }
                 });
-            });
-            loader.load(&dump_path)?;
+            })?;
+
+            // From here on, we know how many crates there are
+            let num_crates = crates.borrow().len();
+            crate_owners.borrow_mut().reserve(num_crates);
 
-            let mut loader = db_dump::Loader::new();
-            loader.teams(|t| {
+            if options.keywords {
+                dump.load("crates_keywords", |ck: db_dump::crates_keywords::Row| {
+                    crates.borrow_mut().entry(ck.crate_id).and_modify(|c| {
+                        keywords.borrow_mut().insert(ck.keyword_id, None);
+                        c.keywords.push(ck.keyword_id);
+                    });
+                })?;
+
+                dump.load("keywords", |k: db_dump::keywords::Row| {
+                    keywords
+                        .borrow_mut()
+                        .entry(k.id)
+                        .and_modify(|kw| *kw = Some(k.keyword.into_boxed_str()));
+                })?;
+            }
+
+            if options.categories {
+                dump.load(
+                    "crates_categories",
+                    |cc: db_dump::crates_categories::Row| {
+                        crates.borrow_mut().entry(cc.crate_id).and_modify(|c| {
+                            categories.borrow_mut().insert(cc.category_id, None);
+                            c.categories.push(cc.category_id);
+                        });
+                    },
+                )?;
+
+                dump.load("categories", |c: db_dump::categories::Row| {
+                    categories.borrow_mut().entry(c.id).and_modify(|category| {
+                        *category = Some(DbDumpCategoryData {
+                            name: c.category.into(),
+                            slug: c.slug.into(),
+                        })
+                    });
+                })?;
+            }
+
+            dump.load("crate_downloads", |cd: db_dump::crate_downloads::Row| {
+                if let Some(c) = crates.borrow_mut().get_mut(&cd.crate_id) {
+                    c.downloads = cd.downloads;
+                }
+            })?;
+
+            // The default versions of all crates, not just the loaded ones,
+            // as they're needed to count the dependents of the loaded crates
+            let default_versions = RefCell::new(FxHashSet::<VersionId>::with_capacity_and_hasher(
+                EXPECTED_CRATES,
+                Default::default(),
+            ));
+            dump.load("default_versions", |dv: db_dump::default_versions::Row| {
+                default_versions.borrow_mut().insert(dv.version_id);
+            })?;
+
+            // Only needed to attribute version downloads to crates
+            let version_crates =
+                RefCell::new(FxHashMap::<VersionId, CrateId>::with_capacity_and_hasher(
+                    if options.download_history {
+                        num_crates * AVERAGE_VERSIONS_PER_CRATE
+                    } else {
+                        0
+                    },
+                    Default::default(),
+                ));
+            dump.load("versions", |v: VersionRow| {
+                let mut crates = crates.borrow_mut();
+                let Some(c) = crates.get_mut(&v.crate_id) else {
+                    return;
+                };
+                if default_versions.borrow().contains(&v.id) {
+                    c.default_version = Some(c.versions.len() as u32);
+                }
+                c.versions.push(DbDumpVersionData {
+                    num: v.num.into(),
+                    license: v.license.map(|l| licenses.borrow_mut().intern(&l)),
+                    rust_version: v.rust_version.map(Into::into),
+                    crate_size: v.crate_size,
+                    created_at: v.created_at,
+                    yanked: v.yanked,
+                });
+                if options.download_history {
+                    version_crates.borrow_mut().insert(v.id, v.crate_id);
+                }
+            })?;
+
+            // A crate counts as a dependent if its default version depends on
+            // the crate, possibly multiple times, e.g. for different targets
+            if options.dependents {
+                let dependencies = RefCell::new(FxHashSet::<(VersionId, CrateId)>::default());
+                dump.load("dependencies", |d: DependencyRow| {
+                    if !default_versions.borrow().contains(&d.version_id) {
+                        return;
+                    }
+                    let mut crates = crates.borrow_mut();
+                    let Some(c) = crates.get_mut(&d.crate_id) else {
+                        return;
+                    };
+                    if dependencies.borrow_mut().insert((d.version_id, d.crate_id)) {
+                        c.dependents += 1;
+                    }
+                })?;
+            }
+            default_versions.take();
+
+            if options.download_history {
+                // Aggregate the daily downloads per crate into weeks starting on Monday
+                let weekly_downloads = RefCell::new(
+                    FxHashMap::<CrateId, BTreeMap<NaiveDate, u64>>::with_capacity_and_hasher(
+                        num_crates,
+                        Default::default(),
+                    ),
+                );
+                let last_date = Cell::new(NaiveDate::MIN);
+                dump.load(
+                    "version_downloads",
+                    |vd: db_dump::version_downloads::Row| {
+                        let Some(crate_id) = version_crates.borrow().get(&vd.version_id).copied()
+                        else {
+                            return;
+                        };
+                        let date = vd.date.naive_utc();
+                        last_date.set(last_date.get().max(date));
+                        let week = week_start(date);
+                        *weekly_downloads
+                            .borrow_mut()
+                            .entry(crate_id)
+                            .or_default()
+                            .entry(week)
+                            .or_default() += vd.downloads;
+                    },
+                )?;
+                version_crates.take();
+
+                let weeks = download_weeks(last_date.get());
+                let weekly_downloads = weekly_downloads.take();
+                for (crate_id, c) in crates.borrow_mut().iter_mut() {
+                    let downloads = weekly_downloads.get(crate_id);
+                    c.weekly_downloads = weekly_totals(downloads, &weeks).into();
+                    // The dump only contains the downloads of the last 90 days
+                    c.recent_downloads = downloads.map(|d| d.values().sum()).unwrap_or_default();
+                }
+            }
+
+            dump.load("teams", |t: db_dump::teams::Row| {
                 crate_owners
                     .borrow_mut()
                     .entry(OwnerId::Team(t.id))
-                    .and_modify(|co| *co = Some(DbDumpCrateOwnerData { avatar: t.avatar }));
-            });
-            loader.load(&dump_path)?;
+                    .and_modify(|co| {
+                        *co = Some(DbDumpCrateOwnerData {
+                            avatar: t.avatar.into(),
+                            login: team_login_name(&t.login).into(),
+                            name: None,
+                        })
+                    });
+            })?;
 
-            let mut loader = db_dump::Loader::new();
-            loader.users(|u| {
+            dump.load("users", |u: db_dump::users::Row| {
                 crate_owners
                     .borrow_mut()
                     .entry(OwnerId::User(u.id))
                     .and_modify(|co| {
                         *co = Some(DbDumpCrateOwnerData {
-                            avatar: u.gh_avatar,
+                            avatar: u.gh_avatar.into(),
+                            login: u.gh_login.into(),
+                            name: u.name.map(Into::into),
                         })
                     });
-            });
-            loader.load(&dump_path)?;
-        }
+            })?;
 
-        let crates = crates.into_inner();
-        let crate_owners = crate_owners.into_inner();
+            if LOAD_STATS.load(Ordering::Relaxed) {
+                dump.print_stats();
+            }
+        }
 
-        let (user_crate_owners, team_crate_owners) = crate_owners
+        // Keywords that were not found in the keywords table are dropped
+        let keywords: FxHashMap<_, _> = keywords
+            .into_inner()
             .into_iter()
-            .partition::<HashMap<_, _>, _>(|(k, _)| matches!(k, OwnerId::User(_)));
-        let user_crate_owners = user_crate_owners
+            .filter_map(|(id, keyword)| Some((id, keyword?)))
+            .collect();
+        let categories: FxHashMap<_, _> = categories
+            .into_inner()
             .into_iter()
-            .map(|(k, v)| {
-                let OwnerId::User(k) = k else {
-                    unreachable!();
-                };
-                (k, v)
-            })
+            .filter_map(|(id, category)| Some((id, category?)))
             .collect();
 
-        let team_crate_owners = team_crate_owners
+        // Store the crates in a compact vector instead of a map
+        let mut crates_by_id = crates.into_inner();
+        let mut crates = Vec::with_capacity(crates_by_id.len());
+        let crate_names: FxHashMap<_, _> = crate_names
             .into_iter()
-            .map(|(k, v)| {
-                let OwnerId::Team(k) = k else {
-                    unreachable!();
-                };
-                (k, v)
+            .filter_map(|(name, id)| {
+                let mut data = crates_by_id.remove(&id)?;
+                data.user_owners.shrink_to_fit();
+                data.team_owners.shrink_to_fit();
+                data.keywords.retain(|id| keywords.contains_key(id));
+                data.keywords
+                    .sort_unstable_by(|a, b| keywords[a].cmp(&keywords[b]));
+                data.keywords.truncate(MAX_KEYWORDS);
+                data.keywords.shrink_to_fit();
+                data.categories.retain(|id| categories.contains_key(id));
+                data.categories
+                    .sort_unstable_by(|a, b| categories[a].slug.cmp(&categories[b].slug));
+                data.categories.shrink_to_fit();
+                crates.push(data);
+                Some((name.into_boxed_str(), crates.len() as u32 - 1))
             })
             .collect();
 
+        // Owners that were not found in the users or teams tables are dropped
+        let mut user_crate_owners = FxHashMap::default();
+        let mut team_crate_owners = FxHashMap::default();
+        for (owner_id, data) in crate_owners.into_inner() {
+            let Some(data) = data else {
+                continue;
+            };
+            match owner_id {
+                OwnerId::User(uid) => user_crate_owners.insert(uid, data),
+                OwnerId::Team(tid) => team_crate_owners.insert(tid, data),
+            };
+        }
+
+        let normalized_names = crate_names
+            .keys()
+            .map(|name| (normalize_crate_name(name).into(), name.clone()))
+            .collect();
+
         Ok(Self {
             crates,
             crate_names,
-            // crate_owners,
+            normalized_names,
+            licenses: licenses.into_inner().into_strings(),
             user_crate_owners,
             team_crate_owners,
+            keywords,
+            categories,
+            dump_timestamp,
         })
     }
 
-    pub async fn preload_all(dump_path: impl AsRef<Path> + Send + 'static) -> Result<Self, Error> {
-        tokio::task::spawn_blocking(|| Self::load_with_filter_blocking(dump_path, LoadFilter::All))
-            .await
-            .unwrap()
+    /// A db without any crates, for when all data is fetched from the crates.io API
+    pub fn empty() -> Self {
+        Self {
+            crates: vec![],
+            crate_names: FxHashMap::default(),
+            normalized_names: FxHashMap::default(),
+            licenses: vec![],
+            user_crate_owners: FxHashMap::default(),
+            team_crate_owners: FxHashMap::default(),
+            keywords: FxHashMap::default(),
+            categories: FxHashMap::default(),
+            dump_timestamp: None,
+        }
     }
 
-    pub async fn preload_many(
-        dump_path: impl AsRef<Path> + Send + 'static,
-        items: HashSet<String>,
-    ) -> Result<Self, Error> {
-        tokio::task::spawn_blocking(|| {
-            Self::load_with_filter_blocking(dump_path, LoadFilter::Select(items))
-        })
-        .await
-        .unwrap()
+    /// Start loading a db from the database dump at `dump_path`
+    pub fn builder(dump_path: impl Into<PathBuf>) -> CrateDbBuilder {
+        CrateDbBuilder {
+            dump_path: dump_path.into(),
+            filter: LoadFilter::All,
+            options: LoadOptions::default(),
+            threads: None,
+        }
     }
 
-    pub async fn preload_one(
-        dump_path: impl AsRef<Path> + Send + 'static,
-        item: String,
-    ) -> Result<Self, Error> {
-        tokio::task::spawn_blocking(|| {
-            Self::load_with_filter_blocking(dump_path, LoadFilter::Single(item))
-        })
-        .await
-        .unwrap()
+    /// Merge supplementary crates, e.g. from a private registry, into this db.
+    /// Crates that are already in the db are replaced. Owners are matched to
+    /// the users in the db by login, and get their GitHub avatar otherwise.
+    pub fn merge(&mut self, crates: impl IntoIterator<Item = SupplementaryCrate>) {
+        let mut user_ids: FxHashMap<Box<str>, UserId> = self
+            .user_crate_owners
+            .iter()
+            .map(|(id, owner)| (owner.login.clone(), *id))
+            .collect();
+        let mut keyword_ids: FxHashMap<Box<str>, KeywordId> = self
+            .keywords
+            .iter()
+            .map(|(id, keyword)| (keyword.clone(), *id))
+            .collect();
+        let mut licenses = Interner::default();
+        for license in std::mem::take(&mut self.licenses) {
+            licenses.intern(&license);
+        }
+        // Supplementary owners and keywords get IDs counting down from
+        // the maximum, which are very unlikely to be used by crates.io
+        let mut next_user_id = u32::MAX;
+        let mut next_keyword_id = u32::MAX;
+
+        for krate in crates {
+            let user_owners = krate
+                .owners
+                .iter()
+                .map(|login| {
+                    *user_ids.entry(login.as_str().into()).or_insert_with(|| {
+                        while self.user_crate_owners.contains_key(&UserId(next_user_id)) {
+                            next_user_id -= 1;
+                        }
+                        let id = UserId(next_user_id);
+                        self.user_crate_owners.insert(
+                            id,
+                            DbDumpCrateOwnerData {
+                                avatar: format!(
+                                    "https://avatars.githubusercontent.com/{login}?v=4"
+                                )
+                                .into(),
+                                login: login.as_str().into(),
+                                name: None,
+                            },
+                        );
+                        id
+                    })
+                })
+                .collect();
+            let mut keywords = krate.keywords;
+            keywords.sort_unstable();
+            keywords.truncate(MAX_KEYWORDS);
+            let keywords = keywords
+                .into_iter()
+                .map(|keyword| {
+                    *keyword_ids
+                        .entry(keyword.as_str().into())
+                        .or_insert_with(|| {
+                            while self.keywords.contains_key(&KeywordId(next_keyword_id)) {
+                                next_keyword_id -= 1;
+                            }
+                            let id = KeywordId(next_keyword_id);
+                            self.keywords.insert(id, keyword.into());
+                            id
+                        })
+                })
+                .collect();
+
+            let created_at = krate.created_at.unwrap_or_default();
+            let updated_at = krate.updated_at.unwrap_or(created_at);
+            let versions: Vec<_> = krate
+                .version
+                .map(|num| DbDumpVersionData {
+                    num: num.into(),
+                    license: krate.license.map(|l| licenses.intern(&l)),
+                    rust_version: None,
+                    crate_size: None,
+                    created_at: updated_at,
+                    yanked: false,
+                })
+                .into_iter()
+                .collect();
+            let data = DbDumpCrateData {
+                description: truncate_description(krate.description).into(),
+                user_owners,
+                team_owners: vec![],
+                weekly_downloads: Box::default(),
+                default_version: (!versions.is_empty()).then_some(0),
+                versions,
+                downloads: krate.downloads,
+                recent_downloads: krate.recent_downloads,
+                dependents: 0,
+                keywords,
+                categories: vec![],
+                repository: krate.repository.map(Into::into),
+                homepage: krate.homepage.map(Into::into),
+                documentation: krate.documentation.map(Into::into),
+                created_at,
+                updated_at,
+            };
+
+            let name = krate.name.into_inner();
+            match self.crate_names.get(name.as_str()) {
+                Some(idx) => self.crates[*idx as usize] = data,
+                None => {
+                    self.crates.push(data);
+                    let idx = self.crates.len() as u32 - 1;
+                    self.normalized_names
+                        .insert(normalize_crate_name(&name).into(), name.as_str().into());
+                    self.crate_names.insert(name.into(), idx);
+                }
+            }
+        }
+        self.licenses = licenses.into_strings();
+    }
+
+    /// Look up a crate by its exact name, or else by its
+    /// [normalized](normalize_crate_name) name, like crates.io does.
+    /// Returns the actual name of the crate along with its data.
+    fn lookup(&self, name: &str) -> Option<(&str, &DbDumpCrateData)> {
+        let (name, idx) = match self.crate_names.get_key_value(name) {
+            Some((name, idx)) => (name, idx),
+            None => {
+                let name = self.normalized_names.get(&*normalize_crate_name(name))?;
+                (name, self.crate_names.get(name)?)
+            }
+        };
+        Some((name, self.crates.get(*idx as usize)?))
     }
 
     pub fn augment_crate_spec(&self, name: CrateName) -> Result<CrateData, Error> {
-        let id = self.crate_names.get(name.as_ref()).ok_or(Error::NotFound)?;
-        let data = &self.crates[id];
+        let (actual_name, data) = self.lookup(name.as_ref()).ok_or(Error::NotFound)?;
+        self.augment(name, actual_name, data, data.current_version())
+    }
+
+    /// Like [`Self::augment_crate_spec`], but with the version-specific data,
+    /// like the license, of `version` instead of the default version.
+    /// Returns [`Error::NotFound`] if the crate has no such version.
+    pub fn augment_crate_version_spec(
+        &self,
+        name: CrateName,
+        version: &str,
+    ) -> Result<CrateData, Error> {
+        let (actual_name, data) = self.lookup(name.as_ref()).ok_or(Error::NotFound)?;
+        let version = data
+            .versions
+            .iter()
+            .find(|v| &*v.num == version)
+            .ok_or(Error::NotFound)?;
+        self.augment(name, actual_name, data, Some(version))
+    }
+
+    /// Augment the crate `name`, called `actual_name` in the db, using
+    /// the version-specific data of `version`.
+    fn augment(
+        &self,
+        name: CrateName,
+        actual_name: &str,
+        data: &DbDumpCrateData,
+        version: Option<&DbDumpVersionData>,
+    ) -> Result<CrateData, Error> {
+        let name = if actual_name == name.as_ref() {
+            name
+        } else {
+            actual_name.parse()?
+        };
 
         let user_owners = data
-            .owners
+            .user_owners
             .iter()
-            .filter_map(|oid| match oid {
-                OwnerId::User(uid) => Some(uid),
-                OwnerId::Team(_) => None,
-            })
-            .flat_map(|uid| self.user_crate_owners[uid].iter())
-            .map(|DbDumpCrateOwnerData { avatar }| UserCrateOwner {
-                avatar: format!("{avatar}&s=70").into(),
-            })
+            .filter_map(|uid| self.user_crate_owners.get(uid))
+            .map(
+                |DbDumpCrateOwnerData {
+                     avatar,
+                     login,
+                     name,
+                 }| UserCrateOwner {
+                    avatar: format!("{avatar}&s=70"),
+                    login: login.to_string(),
+                    name: name.as_deref().map(Into::into),
+                    url: user_profile_url(login),
+                },
+            )
             .take(3)
             .collect();
 
         let team_owners = data
-            .owners
+            .team_owners
             .iter()
-            .filter_map(|oid| match oid {
-                OwnerId::Team(tid) => Some(tid),
-                OwnerId::User(_) => None,
-            })
-            .flat_map(|tid| self.team_crate_owners[tid].iter())
-            .map(|DbDumpCrateOwnerData { avatar }| TeamCrateOwner {
-                avatar: format!("{avatar}&s=70").into(),
-            })
+            .filter_map(|tid| self.team_crate_owners.get(tid))
+            .map(
+                |DbDumpCrateOwnerData { avatar, login, .. }| TeamCrateOwner {
+                    avatar: format!("{avatar}&s=70"),
+                    name: login.to_string(),
+                    org: team_org(login).to_string(),
+                    url: team_profile_url(login),
+                },
+            )
             .take(3)
             .collect();
 
         Ok(CrateData {
             name,
-            description: data.description.clone().into(),
+            description: data.description.to_string(),
             user_owners,
             team_owners,
+            weekly_downloads: data.weekly_downloads.to_vec(),
+            license: version
+                .and_then(|v| v.license)
+                .and_then(|idx| self.licenses.get(idx as usize))
+                .map(ToString::to_string),
+            version: version.map(|v| v.num.to_string()),
+            rust_version: version.and_then(|v| v.rust_version.as_deref().map(Into::into)),
+            crate_size: version.and_then(|v| v.crate_size),
+            published_at: version.map(|v| v.created_at),
+            num_versions: data.versions.len().try_into().unwrap_or(u32::MAX),
+            num_owners: data.user_owners.len() + data.team_owners.len(),
+            downloads: data.downloads,
+            recent_downloads: data.recent_downloads,
+            dependents: data.dependents,
+            keywords: data
+                .keywords
+                .iter()
+                .filter_map(|id| self.keywords.get(id))
+                .map(ToString::to_string)
+                .collect(),
+            categories: data
+                .categories
+                .iter()
+                .filter_map(|id| self.categories.get(id))
+                .map(|DbDumpCategoryData { name, slug }| CrateCategory {
+                    name: name.to_string(),
+                    slug: slug.to_string(),
+                })
+                .collect(),
+            repository: data.repository.as_deref().map(Into::into),
+            homepage: data.homepage.as_deref().map(Into::into),
+            documentation: data.documentation.as_deref().map(Into::into),
+            created_at: data.created_at,
+            updated_at: data.updated_at,
         })
     }
 
+    /// Like [`Self::augment_crate_spec`], but fetches crates that are missing
+    /// from the dump from the crates.io API, if enabled using [`enable_api_fallback`].
+    pub async fn augment_crate_spec_or_fetch(&self, name: CrateName) -> Result<CrateData, Error> {
+        match self.augment_crate_spec(name.clone()) {
+            Err(Error::NotFound) if api_fallback_enabled() => {
+                crates_io::fetch_crate_data(name).await
+            }
+            result => result,
+        }
+    }
+
+    /// Like [`Self::augment_crate_version_spec`], but fetches crates or versions that are
+    /// missing from the dump from the crates.io API, if enabled using [`enable_api_fallback`].
+    pub async fn augment_crate_version_spec_or_fetch(
+        &self,
+        name: CrateName,
+        version: &str,
+    ) -> Result<CrateData, Error> {
+        match self.augment_crate_version_spec(name.clone(), version) {
+            Err(Error::NotFound) if api_fallback_enabled() => {
+                crates_io::fetch_crate_version_data(name, version).await
+            }
+            result => result,
+        }
+    }
+
+    /// When the database dump this db was loaded from was exported, if known
+    pub fn dump_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.dump_timestamp
+    }
+
+    /// Whether a crate with the passed name was loaded
+    pub fn contains(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// The names of all loaded crates
+    pub fn crate_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.crate_names.keys().map(|name| &**name)
+    }
+
+    /// The all-time downloads of the crate called `name`, if it was loaded
+    pub fn downloads(&self, name: &str) -> Option<u64> {
+        self.with_downloads(name).map(|(_, downloads)| downloads)
+    }
+
+    /// The names of up to `limit` crates whose [normalized](normalize_crate_name)
+    /// names start with `prefix`, the most downloaded ones first
+    pub fn search_prefix(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        let prefix = normalize_crate_name(prefix);
+        let mut matches: Vec<_> = self
+            .normalized_names
+            .iter()
+            .filter(|(normalized, _)| normalized.starts_with(&prefix))
+            .filter_map(|(_, name)| self.with_downloads(name))
+            .collect();
+        matches.sort_unstable_by(by_downloads);
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// The names of the `n` most downloaded crates, the most downloaded one first
+    pub fn top_by_downloads(&self, n: usize) -> Vec<&str> {
+        let mut crates: Vec<_> = self
+            .crate_names
+            .keys()
+            .filter_map(|name| self.with_downloads(name))
+            .collect();
+        if n < crates.len() {
+            crates.select_nth_unstable_by(n, by_downloads);
+            crates.truncate(n);
+        }
+        crates.sort_unstable_by(by_downloads);
+        crates.into_iter().map(|(name, _)| name).collect()
+    }
+
+    /// Drop all crates but the `n` most downloaded ones
+    pub fn retain_top_by_downloads(&mut self, n: usize) {
+        let top: FxHashSet<String> = self
+            .top_by_downloads(n)
+            .into_iter()
+            .map(String::from)
+            .collect();
+        self.retain_by(|name, _| top.contains(name));
+    }
+
+    /// The names of `n` crates picked at random, or of all crates if there are fewer
+    pub fn random(&self, n: usize) -> Vec<&str> {
+        fastrand::choose_multiple(self.crate_names.keys().map(|name| &**name), n)
+    }
+
+    /// The name of the crate called `name` along with its all-time downloads
+    fn with_downloads(&self, name: &str) -> Option<(&str, u64)> {
+        let (name, idx) = self.crate_names.get_key_value(name)?;
+        Some((name, self.crates.get(*idx as usize)?.downloads))
+    }
+
+    /// Compute which crates differ between this db and `new`. Crates are
+    /// considered changed if any of the data used for rendering changed.
+    pub fn diff(&self, new: &CrateDb) -> CrateDbDiff {
+        let mut diff = CrateDbDiff::default();
+        for name in new.crate_names.keys() {
+            if !self.crate_names.contains_key(name) {
+                diff.added.push(name.to_string());
+                continue;
+            }
+            let Ok(spec) = name.parse::<CrateName>() else {
+                continue;
+            };
+            if self.augment_crate_spec(spec.clone()).ok() != new.augment_crate_spec(spec).ok() {
+                diff.changed.push(name.to_string());
+            }
+        }
+        diff.removed = self
+            .crate_names
+            .keys()
+            .filter(|name| !new.crate_names.contains_key(*name))
+            .map(ToString::to_string)
+            .collect();
+        diff
+    }
+
+    /// Reload the full database dump at `dump_path` into this db,
+    /// returning which crates were added, changed or removed.
+    pub async fn reload(
+        &mut self,
+        dump_path: impl AsRef<Path> + Send + 'static,
+    ) -> Result<CrateDbDiff, Error> {
+        let new = Self::builder(dump_path.as_ref()).load().await?;
+        Ok(self.replace(new))
+    }
+
+    /// Replace the contents of this db with `new`, returning
+    /// which crates were added, changed or removed.
+    pub fn replace(&mut self, new: CrateDb) -> CrateDbDiff {
+        let diff = self.diff(&new);
+        *self = new;
+        diff
+    }
+
     /// Returns an iterator over all preloaded crates, augmented
-    pub fn augment_preloaded(&self) -> impl Iterator<Item = CrateData> + '_ {
+    pub fn augment_preloaded(&self) -> impl Iterator<Item = Result<CrateData, Error>> + '_ {
         self.crate_names
             .keys()
-            .map(|k| self.augment_crate_spec(k.parse().unwrap()).unwrap())
+            .map(|k| self.augment_preloaded_crate(k))
+    }
+
+    /// Like [`Self::augment_preloaded`], but augments the crates in parallel
+    pub fn par_augment_preloaded(
+        &self,
+    ) -> impl ParallelIterator<Item = Result<CrateData, Error>> + '_ {
+        self.crate_names
+            .par_iter()
+            .map(|(k, _)| self.augment_preloaded_crate(k))
+    }
+
+    fn augment_preloaded_crate(&self, name: &str) -> Result<CrateData, Error> {
+        self.augment_crate_spec(name.parse()?)
+    }
+}
+
+/// Orders crates by their downloads, descending, and then by name
+fn by_downloads((a_name, a): &(&str, u64), (b_name, b): &(&str, u64)) -> std::cmp::Ordering {
+    b.cmp(a).then_with(|| a_name.cmp(b_name))
+}
+
+/// The key identifying a cache of `dump`. Includes the crate version,
+/// as the layout of [`CrateDb`] may change between versions.
+pub(crate) fn cache_key(dump: &DbDump) -> Result<u64, Error> {
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    dump.fingerprint()?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// The canonical form of a crate name, under which crates.io considers names
+/// equal: lowercase, with hyphens replaced by underscores.
+pub(crate) fn normalize_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+/// Whether two crate names are equal after normalization, without allocating
+fn crate_names_match(a: &str, b: &str) -> bool {
+    let fold = |c: u8| match c {
+        b'-' => b'_',
+        c => c.to_ascii_lowercase(),
+    };
+    a.len() == b.len() && a.bytes().zip(b.bytes()).all(|(a, b)| fold(a) == fold(b))
+}
+
+/// Turn a team login as found in the db dump, e.g. `github:rust-lang:libs`,
+/// into the `org/team` form, e.g. `rust-lang/libs`.
+pub(crate) fn team_login_name(login: &str) -> String {
+    let login = login.strip_prefix("github:").unwrap_or(login);
+    login.replace(':', "/")
+}
+
+/// The organization of a team in the `org/team` form
+pub(crate) fn team_org(name: &str) -> &str {
+    name.split_once('/').map_or(name, |(org, _)| org)
+}
+
+/// The URL of a user's GitHub profile
+pub(crate) fn user_profile_url(login: &str) -> String {
+    format!("https://github.com/{login}")
+}
+
+/// The URL of a team's page on GitHub, given its name in the `org/team` form
+pub(crate) fn team_profile_url(name: &str) -> String {
+    match name.split_once('/') {
+        Some((org, team)) => format!("https://github.com/orgs/{org}/teams/{team}"),
+        None => format!("https://github.com/{name}"),
+    }
+}
+
+/// Cut off a description if it's too long, and collapse its whitespace.
+/// Sadly typst doesn't seem to provide a nice way to do this.
+pub(crate) fn truncate_description(mut description: String) -> String {
+    if let Some((idx, _)) = description.char_indices().nth(110) {
+        let idx = description[..idx]
+            .rfind([' ', ',', '.', ';', '!', '?'])
+            .unwrap_or(idx);
+        description.truncate(idx);
+        description.push('…');
+    };
+    description.trim().replace(|c: char| c.is_whitespace(), " ")
+}
+
+/// The Monday of the week `date` is in
+pub(crate) fn week_start(date: NaiveDate) -> NaiveDate {
+    date.week(Weekday::Mon).first_day()
+}
+
+/// The download totals for each of `weeks`, given the totals per week
+pub(crate) fn weekly_totals(
+    downloads: Option<&BTreeMap<NaiveDate, u64>>,
+    weeks: &[NaiveDate],
+) -> Vec<u64> {
+    weeks
+        .iter()
+        .map(|week| {
+            downloads
+                .and_then(|d| d.get(week))
+                .copied()
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// The start dates of the last [`SPARKLINE_WEEKS`] weeks that
+/// were complete as of `last_date`, oldest first.
+pub(crate) fn download_weeks(last_date: NaiveDate) -> Vec<NaiveDate> {
+    if last_date == NaiveDate::MIN {
+        // No download data was loaded at all
+        return vec![];
+    }
+    let mut week = last_date.week(Weekday::Mon).first_day();
+    if last_date.weekday() != Weekday::Sun {
+        // The current week is not over yet
+        week = week - Days::new(7);
+    }
+    let mut weeks: Vec<_> = std::iter::successors(Some(week), |w| w.checked_sub_days(Days::new(7)))
+        .take(SPARKLINE_WEEKS)
+        .collect();
+    weeks.reverse();
+    weeks
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate};
+    use db_dump::{keywords::KeywordId, users::UserId};
+    use rayon::iter::ParallelIterator;
+    use rustc_hash::FxHashMap;
+    use test_case::case;
+
+    use super::{
+        CrateDb, DataFilter, DbDumpCrateData, DbDumpCrateOwnerData, DbDumpVersionData, LoadFilter,
+        LoadOptions,
+    };
+    use crate::supplement::SupplementaryCrate;
+
+    /// Build a [`CrateDb`] of crates with a single user owner each
+    fn test_db(crates: &[(&str, u32, &str)]) -> CrateDb {
+        let mut db = CrateDb {
+            crates: vec![],
+            crate_names: FxHashMap::default(),
+            normalized_names: FxHashMap::default(),
+            licenses: vec!["MIT".into()],
+            user_crate_owners: FxHashMap::default(),
+            team_crate_owners: FxHashMap::default(),
+            keywords: FxHashMap::default(),
+            categories: FxHashMap::default(),
+            dump_timestamp: None,
+        };
+        for (idx, (name, uid, description)) in (0..).zip(crates) {
+            let data = DbDumpCrateData {
+                description: (*description).into(),
+                user_owners: vec![UserId(*uid)],
+                team_owners: vec![],
+                weekly_downloads: [1, 2, 3].into(),
+                versions: ["0.1.0", "0.2.0", "1.0.0"]
+                    .map(|num| DbDumpVersionData {
+                        num: num.into(),
+                        license: Some(0),
+                        rust_version: None,
+                        crate_size: Some(1024),
+                        created_at: DateTime::UNIX_EPOCH,
+                        yanked: false,
+                    })
+                    .into(),
+                default_version: Some(2),
+                downloads: 6,
+                recent_downloads: 6,
+                dependents: 2,
+                keywords: vec![KeywordId(*uid)],
+                categories: vec![],
+                repository: None,
+                homepage: None,
+                documentation: None,
+                created_at: DateTime::UNIX_EPOCH,
+                updated_at: DateTime::UNIX_EPOCH,
+            };
+            let owner = DbDumpCrateOwnerData {
+                avatar: format!("https://avatars.githubusercontent.com/u/{uid}?v=4").into(),
+                login: format!("user-{uid}").into(),
+                name: None,
+            };
+            db.crates.push(data);
+            db.crate_names.insert((*name).into(), idx);
+            db.normalized_names
+                .insert(super::normalize_crate_name(name).into(), (*name).into());
+            db.user_crate_owners.insert(UserId(*uid), owner);
+            db.keywords
+                .insert(KeywordId(*uid), format!("keyword-{uid}").into());
+        }
+        db
+    }
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let db = test_db(&[("a", 1, "A crate"), ("b", 2, "A crate")]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crate-db-0.bin");
+        db.write_cache(&path).unwrap();
+        let mut db = CrateDb::read_cache(&path).unwrap();
+
+        db.retain(&LoadFilter::Single("b".into()));
+        assert_eq!(db.crate_names.len(), 1);
+        assert_eq!(db.crates.len(), 1);
+        assert!(db.user_crate_owners.contains_key(&UserId(2)));
+        assert!(!db.user_crate_owners.contains_key(&UserId(1)));
+        assert_eq!(db.keywords.len(), 1);
+        let data = db.augment_crate_spec("b".parse().unwrap()).unwrap();
+        assert_eq!(data.weekly_downloads, [1, 2, 3]);
+        assert_eq!(data.num_owners, 1);
+        assert_eq!(data.license.as_deref(), Some("MIT"));
+        assert_eq!(data.version.as_deref(), Some("1.0.0"));
+        assert_eq!(data.num_versions, 3);
+        assert_eq!(data.keywords, ["keyword-2"]);
+    }
+
+    #[test]
+    fn test_augment_without_default_version() {
+        let mut db = test_db(&[("a", 1, "A crate")]);
+        db.crates[0].default_version = None;
+
+        // Freshly created crates may not have a default version yet
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert_eq!(data.version, None);
+        assert_eq!(data.license, None);
+        assert_eq!(data.alt_text(), "a — A crate. 1 owner");
+    }
+
+    #[test]
+    fn test_augment_version() {
+        let mut db = test_db(&[("a", 1, "A crate")]);
+        db.crates[0].versions[0].license = None;
+        db.crates[0].versions[0].rust_version = Some("1.70".into());
+
+        let data = db
+            .augment_crate_version_spec("a".parse().unwrap(), "0.1.0")
+            .unwrap();
+        assert_eq!(data.version.as_deref(), Some("0.1.0"));
+        assert_eq!(data.license, None);
+        assert_eq!(data.rust_version.as_deref(), Some("1.70"));
+        assert_eq!(data.num_versions, 3);
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert_eq!(data.version.as_deref(), Some("1.0.0"));
+        assert_eq!(data.rust_version, None);
+        assert!(db
+            .augment_crate_version_spec("a".parse().unwrap(), "2.0.0")
+            .is_err());
+    }
+
+    #[test]
+    fn test_yanked_default_version() {
+        let mut db = test_db(&[("a", 1, "A crate")]);
+        let versions = &mut db.crates[0].versions;
+        for (v, days) in versions.iter_mut().zip([1, 3, 2]) {
+            v.created_at += chrono::TimeDelta::days(days);
+        }
+        versions[2].yanked = true;
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert_eq!(data.version.as_deref(), Some("0.2.0"));
+
+        // If every version was yanked, the default version is still shown
+        for v in &mut db.crates[0].versions {
+            v.yanked = true;
+        }
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert_eq!(data.version.as_deref(), Some("1.0.0"));
+    }
+
+    #[test]
+    fn test_inconsistent_db() {
+        let mut db = test_db(&[("a", 1, "A crate"), ("b", 2, "B crate")]);
+        db.crates[0].versions[2].license = Some(42);
+        db.crate_names.insert("c".into(), 42);
+        db.user_crate_owners.clear();
+
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert_eq!(data.license, None);
+        assert!(data.user_owners.is_empty());
+        assert!(db.augment_crate_spec("c".parse().unwrap()).is_err());
+        assert_eq!(db.augment_preloaded().count(), 3);
+        assert_eq!(db.augment_preloaded().filter(Result::is_ok).count(), 2);
+        assert_eq!(db.par_augment_preloaded().filter(Result::is_ok).count(), 2);
+
+        db.retain(&LoadFilter::All);
+        assert_eq!(db.crate_names.len(), 2);
+    }
+
+    #[test]
+    fn test_drop_skipped() {
+        let mut db = test_db(&[("a", 1, "A crate")]);
+        db.drop_skipped(
+            LoadOptions::default()
+                .keywords(false)
+                .download_history(false),
+        );
+        let data = db.augment_crate_spec("a".parse().unwrap()).unwrap();
+        assert!(data.keywords.is_empty());
+        assert!(data.weekly_downloads.is_empty());
+        assert_eq!(data.recent_downloads, 0);
+        assert_eq!(data.dependents, 2);
+        assert!(db.keywords.is_empty());
+
+        let options = LoadOptions::default().keywords(false).dependents(false);
+        assert!(!options.is_all());
+        assert_eq!(options.tables(), super::LOADED_TABLES - 3);
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut interner = super::Interner::default();
+        assert_eq!(interner.intern("MIT"), 0);
+        assert_eq!(interner.intern("Apache-2.0"), 1);
+        assert_eq!(interner.intern("MIT"), 0);
+        assert_eq!(interner.into_strings().len(), 2);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = test_db(&[
+            ("a", 1, "A crate"),
+            ("b", 2, "B crate"),
+            ("c", 3, "C crate"),
+        ]);
+        let new = test_db(&[
+            ("a", 1, "A crate"),
+            ("b", 2, "Better crate"),
+            ("d", 4, "D crate"),
+        ]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, ["d"]);
+        assert_eq!(diff.changed, ["b"]);
+        assert_eq!(diff.removed, ["c"]);
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut db = test_db(&[("a", 1, "A crate"), ("b", 2, "A crate")]);
+        let new = test_db(&[
+            ("a", 1, "A crate"),
+            ("b", 2, "B crate"),
+            ("c", 3, "A crate"),
+        ]);
+        let diff = db.replace(new);
+        assert_eq!(diff.added, ["c"]);
+        assert_eq!(diff.changed, ["b"]);
+        assert!(diff.removed.is_empty());
+        assert!(db.contains("c"));
+        assert_eq!(db.replace(test_db(&[])).removed.len(), 3);
+    }
+
+    #[test]
+    fn test_download_weeks() {
+        let weeks = super::download_weeks(NaiveDate::from_ymd_opt(2025, 1, 15).unwrap());
+        assert_eq!(weeks.len(), super::SPARKLINE_WEEKS);
+        assert_eq!(weeks.last(), NaiveDate::from_ymd_opt(2025, 1, 6).as_ref());
+        assert_eq!(
+            weeks.first(),
+            NaiveDate::from_ymd_opt(2024, 10, 21).as_ref()
+        );
+
+        let weeks = super::download_weeks(NaiveDate::from_ymd_opt(2025, 1, 19).unwrap());
+        assert_eq!(weeks.last(), NaiveDate::from_ymd_opt(2025, 1, 13).as_ref());
+
+        assert!(super::download_weeks(NaiveDate::MIN).is_empty());
+    }
+
+    #[case("tokio-*", "tokio-util" => true)]
+    #[case("tokio-*", "tokio" => false)]
+    #[case("serde*json", "serde_json" => true)]
+    #[case("serde*json", "serde_yaml" => false)]
+    #[case("?ex", "hex" => true)]
+    fn test_pattern_filter(pattern: &str, name: &str) -> bool {
+        LoadFilter::from_pattern(pattern).unwrap().matches(name)
+    }
+
+    #[case("^serde(_|$)", "serde" => true)]
+    #[case("^serde(_|$)", "serde_json" => true)]
+    #[case("^serde(_|$)", "serdeq" => false)]
+    fn test_regex_filter(regex: &str, name: &str) -> bool {
+        LoadFilter::Regex(regex.parse().unwrap()).matches(name)
+    }
+
+    #[test]
+    fn test_retain_data() {
+        let mut db = test_db(&[("a", 1, "A crate"), ("b", 2, "B crate")]);
+        db.retain_data(&DataFilter {
+            keywords: vec!["keyword-2".into()],
+            ..Default::default()
+        });
+        assert!(!db.contains("a"));
+        assert!(db.contains("b"));
+        assert_eq!(db.keywords.len(), 1);
+    }
+
+    #[case(&["web-programming"], "web-programming" => true)]
+    #[case(&["web-programming"], "web-programming::http-server" => true)]
+    #[case(&["web-programming"], "web-programming-tools" => false)]
+    #[case(&["web-programming::http-server"], "web-programming" => false)]
+    #[case(&[], "anything" => true)]
+    fn test_category_filter(categories: &[&str], slug: &str) -> bool {
+        let filter = DataFilter {
+            categories: categories.iter().map(ToString::to_string).collect(),
+            ..Default::default()
+        };
+        filter.matches_parts([slug].into_iter(), std::iter::empty(), 0)
+    }
+
+    #[test]
+    fn test_min_downloads_filter() {
+        let mut db = test_db(&[("a", 1, "A crate")]);
+        db.crates[0].downloads = 100;
+        let filter = |min_downloads| DataFilter {
+            min_downloads,
+            ..Default::default()
+        };
+        db.retain_data(&filter(100));
+        assert!(db.contains("a"));
+        db.retain_data(&filter(101));
+        assert!(!db.contains("a"));
+    }
+
+    #[test]
+    fn test_prefix_pattern() {
+        assert!(matches!(
+            LoadFilter::from_pattern("tokio-*"),
+            Ok(LoadFilter::Prefix(prefix)) if prefix == "tokio-"
+        ));
+        assert!(matches!(
+            LoadFilter::from_pattern("*-sys"),
+            Ok(LoadFilter::Glob(_))
+        ));
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut db = test_db(&[
+            ("serde", 1, "A crate"),
+            ("serde_json", 2, "A crate"),
+            ("serde-yaml", 3, "A crate"),
+            ("tokio", 4, "A crate"),
+        ]);
+        for (idx, downloads) in [(0, 30), (1, 20), (2, 5), (3, 25)] {
+            db.crates[idx].downloads = downloads;
+        }
+
+        assert_eq!(
+            db.search_prefix("serde", 10),
+            ["serde", "serde_json", "serde-yaml"]
+        );
+        assert_eq!(db.search_prefix("Serde-", 1), ["serde_json"]);
+        assert!(db.search_prefix("rand", 10).is_empty());
+
+        assert_eq!(db.top_by_downloads(2), ["serde", "tokio"]);
+        assert_eq!(db.top_by_downloads(10).len(), 4);
+
+        let random = db.random(3);
+        assert_eq!(random.len(), 3);
+        assert!(random.iter().all(|name| db.contains(name)));
+        assert_eq!(db.random(10).len(), 4);
+
+        db.retain_top_by_downloads(2);
+        assert!(db.contains("serde") && db.contains("tokio"));
+        assert!(!db.contains("serde_json"));
+        assert_eq!(
+            db.augment_crate_spec("tokio".parse().unwrap())
+                .unwrap()
+                .downloads,
+            25
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut db = test_db(&[("a", 1, "A crate"), ("b", 2, "A crate")]);
+        let supplementary = |name: &str, owners: &[&str]| SupplementaryCrate {
+            name: name.parse().unwrap(),
+            description: "An internal crate".into(),
+            owners: owners.iter().map(ToString::to_string).collect(),
+            version: Some("0.1.0".into()),
+            license: Some("Proprietary".into()),
+            downloads: 10,
+            recent_downloads: 5,
+            keywords: vec!["keyword-1".into(), "internal".into()],
+            repository: None,
+            homepage: None,
+            documentation: None,
+            created_at: None,
+            updated_at: None,
+        };
+        db.merge([
+            supplementary("b", &["user-1"]),
+            supplementary("internal", &["alice"]),
+        ]);
+
+        let b = db.augment_crate_spec("b".parse().unwrap()).unwrap();
+        assert_eq!(b.description, "An internal crate");
+        assert_eq!(b.license.as_deref(), Some("Proprietary"));
+        assert_eq!(b.keywords, ["internal", "keyword-1"]);
+        // Existing owners keep their avatar
+        assert_eq!(
+            b.user_owners[0].avatar,
+            "https://avatars.githubusercontent.com/u/1?v=4&s=70"
+        );
+
+        let internal = db.augment_crate_spec("internal".parse().unwrap()).unwrap();
+        assert_eq!(internal.user_owners[0].login, "alice");
+        assert_eq!(
+            internal.user_owners[0].avatar,
+            "https://avatars.githubusercontent.com/alice?v=4&s=70"
+        );
+        assert_eq!(internal.num_versions, 1);
+        assert_eq!(
+            db.augment_crate_spec("a".parse().unwrap())
+                .unwrap()
+                .license
+                .as_deref(),
+            Some("MIT")
+        );
+        assert_eq!(db.top_by_downloads(3), ["b", "internal", "a"]);
+    }
+
+    #[test]
+    fn test_normalized_lookup() {
+        let db = test_db(&[("serde_json", 1, "JSON"), ("Inflector", 2, "Inflect")]);
+        for (spec, name) in [
+            ("serde_json", "serde_json"),
+            ("serde-json", "serde_json"),
+            ("Serde-JSON", "serde_json"),
+            ("inflector", "Inflector"),
+        ] {
+            let data = db.augment_crate_spec(spec.parse().unwrap()).unwrap();
+            assert_eq!(data.name.as_ref(), name);
+        }
+        assert!(db.augment_crate_spec("serdejson".parse().unwrap()).is_err());
+        assert!(LoadFilter::Single("Serde-Json".into()).matches("serde_json"));
+        assert!(LoadFilter::select(["Serde-Json".into()]).matches("serde_json"));
+
+        let mut db = db;
+        db.retain(&LoadFilter::select(["inflector".into(), "nope".into()]));
+        assert_eq!(db.crate_names().collect::<Vec<_>>(), ["Inflector"]);
+    }
+
+    #[case("github:rust-lang:libs" => "rust-lang/libs")]
+    #[case("github:serde-rs:owners" => "serde-rs/owners")]
+    #[case("rust-lang/libs" => "rust-lang/libs")]
+    fn test_team_login_name(login: &str) -> String {
+        super::team_login_name(login)
+    }
+
+    #[case("rust-lang/libs" => "rust-lang")]
+    #[case("rust-lang" => "rust-lang")]
+    fn test_team_org(name: &str) -> &str {
+        super::team_org(name)
+    }
+
+    #[case("rust-lang/libs" => "https://github.com/orgs/rust-lang/teams/libs")]
+    #[case("rust-lang" => "https://github.com/rust-lang")]
+    fn test_team_profile_url(name: &str) -> String {
+        super::team_profile_url(name)
     }
 }
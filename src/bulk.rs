@@ -1,6 +1,24 @@
-use std::{fmt, path::PathBuf, pin::pin, str::FromStr, sync::Arc, task::Poll, time::Duration, vec};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    pin::pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+    vec,
+};
 
 use futures_lite::{stream, FutureExt, Stream, StreamExt};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use sha2::{Digest, Sha256};
 use tokio::{
     fs::File,
     io::{self, stdin, AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader, Lines, Stdin},
@@ -8,10 +26,14 @@ use tokio::{
 };
 
 use crate::{
-    augment::CrateDb,
+    archive::{ArchiveFormat, ArchiveWriter},
+    augment::{normalize_crate_name, truncate_description, CrateDb, DataFilter, LoadFilter},
+    convert::{self, CrateData, ImageSize, RenderOptions},
     error::Error,
+    object_store::{Bucket, BucketOptions, BucketUrl},
+    parse_duration,
     spec::{CrateName, InvalidCrateName},
-    CommonArgs,
+    template, CommonArgs,
 };
 
 #[derive(Debug, clap::Args)]
@@ -19,70 +41,1590 @@ pub struct Bulk {
     /// Force overwrite the output.
     #[arg(env, long, short)]
     pub force: bool,
-    /// The number of images to render per second.
-    #[arg(env, long, short, default_value_t = 1)]
+    /// Skip crates whose images were already written to the output folder, e.g. by an
+    /// interrupted run, instead of failing on them. Has no effect with `--force`, archives or buckets
+    #[arg(env, long)]
+    pub skip_existing: bool,
+    /// The maximum number of avatars to fetch from GitHub per second, across all renders.
+    /// Avatars that were fetched before don't count
+    #[arg(env, long, short, default_value_t = 10)]
     pub rate: u64,
-    /// Input specifier. Either a comma-separated list of crate names, a path to a file containing a newline-separated list of crate names, or `-`, indicating stdin.
-    /// Will first attempt to match input with `-`, then parse it as a comma-separated list of crate names, and then fall back to a path, only failing if an empty
-    /// value is passed.
-    #[arg(env, long = "in", short)]
-    pub input: BulkInput,
-    /// The path of the folder to which the PNGs should be written
+    /// Input specifier. Either a comma-separated list of crate names, a glob pattern like `tokio-*` matching crates in the database dump, a path to a file containing
+    /// a newline-separated list of crate names, or `-`, indicating stdin. Will first attempt to match input with `-`, then as a pattern if it contains `*`, `?` or `[`,
+    /// then parse it as a comma-separated list of crate names, and then fall back to a path, only failing if an empty value is passed.
+    /// Paths ending in `.jsonl` or `.csv` are read as records with a `name` and, optionally, a `description`, `theme` and `out` name overriding those of the crate.
+    #[arg(
+        env,
+        long = "in",
+        short,
+        required_unless_present_any = ["all", "top", "filter_regex", "category", "keyword", "min_downloads"]
+    )]
+    pub input: Option<BulkInput>,
+    /// Render every crate in the database dump, instead of reading crate names from `--in`
+    #[arg(env, long, conflicts_with_all = ["input", "filter_regex"])]
+    pub all: bool,
+    /// Only render this many of the most downloaded crates, the most downloaded ones first.
+    /// Without `--in`, these are taken from all crates in the database dump
+    #[arg(env, long)]
+    pub top: Option<usize>,
+    /// Render all crates in the database dump whose name matches this regular
+    /// expression, e.g. `^serde(_|$)`, instead of reading crate names from `--in`
+    #[arg(env, long, conflicts_with = "input")]
+    pub filter_regex: Option<regex::Regex>,
+    /// Comma-separated list of category slugs, e.g. `asynchronous`. Only crates in
+    /// one of these categories or their subcategories are rendered
+    #[arg(env, long, value_delimiter = ',')]
+    pub category: Vec<String>,
+    /// Comma-separated list of keywords, e.g. `embedded`. Only crates with
+    /// one of these keywords are rendered
+    #[arg(env, long, value_delimiter = ',')]
+    pub keyword: Vec<String>,
+    /// Only render crates with at least this many all-time downloads. Without
+    /// `--in`, this renders every crate in the database dump above the threshold
+    #[arg(env, long, default_value_t = 0)]
+    pub min_downloads: u64,
+    /// The path of the folder to which the PNGs should be written. Paths ending in `.tar`,
+    /// `.tar.gz`, `.tar.zst` or `.zip` are written to as an archive instead, and
+    /// `s3://bucket/prefix` or `gs://bucket/prefix` URLs are uploaded to object storage
     #[arg(env, long = "out", short)]
     pub out_folder: PathBuf,
+    /// Where to write each crate's images, relative to the output, e.g. `{first2}/{name}.png`.
+    /// Supports `{name}`, `{first1}` and `{first2}` for the first characters of the name,
+    /// `{version}` and `{size}`, which is required when rendering multiple sizes
+    #[arg(env, long)]
+    pub out_pattern: Option<OutPattern>,
+    /// Write a static HTML page showing all rendered images to this path in the output,
+    /// e.g. `index.html`, to review them after a template change
+    #[arg(env, long)]
+    pub gallery: Option<String>,
+    /// Write a `{name}.txt` file with alternative text next to each PNG
+    #[arg(env, long)]
+    pub alt_text: bool,
+    /// A shell command to run for each image written to the output folder, with `{path}`
+    /// replaced by the image's path, e.g. `oxipng {path}`. Failures are listed in the manifest
+    #[arg(env, long)]
+    pub post_cmd: Option<String>,
+    /// Write a `{name}.json` file next to each PNG, with the data the crate was rendered from
+    #[arg(env, long)]
+    pub with_metadata: bool,
+    /// Comma-separated list of image sizes to render for each crate. Sizes other
+    /// than `og` are written to `{name}-{size}.png`
+    #[arg(env, long, value_delimiter = ',', default_value = "og")]
+    pub sizes: Vec<ImageSize>,
+    /// The number of images to render at the same time. Defaults to the number of CPUs
+    #[arg(env, long, short)]
+    pub jobs: Option<usize>,
+    /// How often to retry rendering a crate that failed, e.g. because an avatar couldn't be fetched
+    #[arg(env, long, default_value_t = 2)]
+    pub retries: u32,
+    /// How long to wait before retrying a failed crate, e.g. `1s`. Doubles with each retry
+    #[arg(env, long, default_value = "1s", value_parser = parse_duration)]
+    pub retry_backoff: Duration,
+    /// Only render the crates in shard `i` out of `n`, e.g. `2/4`, to split a bulk job
+    /// across machines. Every machine should be passed the same input
+    #[arg(env, long)]
+    pub shard: Option<Shard>,
+    /// Fail on the first invalid crate name in the input, instead
+    /// of skipping it and listing it in the manifest
+    #[arg(env, long)]
+    pub strict: bool,
+    /// Keep rendering the remaining crates after one fails, and print a summary
+    /// of the failures at the end. Without this, no new crates are rendered
+    /// after the first failure
+    #[arg(env, long)]
+    pub keep_going: bool,
+    /// The endpoint of S3-compatible object storage to upload to, e.g. for MinIO or R2
+    #[arg(long, env = "AWS_ENDPOINT_URL")]
+    pub bucket_endpoint: Option<reqwest::Url>,
+    /// The region of the bucket to upload to. Defaults to `us-east-1` for S3
+    #[arg(long, env = "AWS_REGION")]
+    pub bucket_region: Option<String>,
+    /// The number of uploads to object storage to run at the same time
+    #[arg(env, long, default_value_t = 32)]
+    pub upload_concurrency: usize,
+    /// The `Cache-Control` metadata of objects uploaded to object storage,
+    /// e.g. `public, max-age=86400`
+    #[arg(env, long)]
+    pub cache_control: Option<String>,
+    /// Don't show a progress bar, e.g. for CI logs
+    #[arg(env, long, short)]
+    pub quiet: bool,
+    /// Write a `.hash` file next to each PNG, with a digest of the data, template and theme
+    /// it was rendered from. Crates whose images in the output folder have matching
+    /// `.hash` files are skipped, so only crates that changed are rendered again
+    #[arg(env, long)]
+    pub hash_sidecars: bool,
+    /// Append each crate to this file as soon as it's finished, so a run
+    /// that gets interrupted can be continued using `--resume`
+    #[arg(env, long)]
+    pub checkpoint: Option<PathBuf>,
+    /// Skip the crates that were finished according to the `--checkpoint` file of an
+    /// interrupted run, and carry them over into the manifest. Not supported for archives
+    #[arg(env, long, requires = "checkpoint")]
+    pub resume: bool,
+    /// The `manifest.json` of a previous run. Crates whose data didn't change since then
+    /// are skipped, and carried over into the new manifest. Usually combined with `--force`
+    #[arg(env, long)]
+    pub since: Option<PathBuf>,
+    /// Load the crates and print what would be rendered, including
+    /// listed crates that weren't found, without rendering or writing anything
+    #[arg(env, long)]
+    pub dry_run: bool,
+    /// Check the images of the crates in the output folder instead of rendering them, reporting
+    /// missing, corrupt or wrongly sized ones. With `--hash-sidecars`, also reports stale images
+    #[arg(env, long, conflicts_with_all = ["dry_run", "skip_existing", "since", "resume"])]
+    pub verify: bool,
 }
 
 impl Bulk {
-    pub async fn run(self, common: CommonArgs) -> Result<(), Error> {
-        let stream = self.input.into_stream().await?;
-        let items = stream
-            .map(|r| r.map(CrateName::into_inner))
-            .try_collect()
-            .await
-            .unwrap();
-        tokio::fs::create_dir_all(&self.out_folder).await?;
+    pub async fn run(mut self, common: CommonArgs) -> Result<(), Error> {
+        let start = Instant::now();
+        // Each size is rendered once, even if it's passed twice
+        let mut sizes = HashSet::new();
+        self.sizes.retain(|&size| sizes.insert(size));
+        common.load_template().await?;
+        if let Some(gallery) = &self.gallery {
+            if gallery.starts_with('/') || gallery.split(['/', '\\']).any(|segment| segment == "..")
+            {
+                return Err(
+                    anyhow::anyhow!("--gallery must be a relative path within the output").into(),
+                );
+            }
+        }
+        if let Some(pattern) = &self.out_pattern {
+            if self.sizes.len() > 1 && !pattern.uses("size") {
+                return Err(anyhow::anyhow!(
+                    "--out-pattern must contain {{size}} to render multiple sizes"
+                )
+                .into());
+            }
+        }
+        let data_filter = DataFilter {
+            categories: self.category.clone(),
+            keywords: self.keyword.clone(),
+            min_downloads: self.min_downloads,
+        };
+        let (mut filter, rejected_input) =
+            load_filter(self.input.clone(), self.filter_regex.clone(), self.strict).await?;
+        let records: HashMap<String, CrateRecord> = match &self.input {
+            Some(BulkInput::Records(path)) => read_records(path)
+                .await?
+                .into_iter()
+                .map(|record| (normalize_crate_name(record.name.as_ref()), record))
+                .collect(),
+            _ => HashMap::new(),
+        };
+        let records = Arc::new(records);
+        // The name the output files of a crate are called after
+        let out_name = |name: &str| {
+            records
+                .get(&normalize_crate_name(name))
+                .and_then(|record| record.out.clone())
+                .unwrap_or_else(|| name.to_string())
+        };
+        let theme = |name: &str| {
+            records
+                .get(&normalize_crate_name(name))
+                .and_then(|record| record.theme.clone())
+        };
+        // Crates that were finished by an interrupted run
+        let resumed: HashMap<String, ManifestEntry> = match (&self.checkpoint, self.resume) {
+            (Some(path), true) => read_checkpoint(path)
+                .await?
+                .into_iter()
+                .map(|entry| (entry.name.clone(), entry))
+                .collect(),
+            _ => HashMap::new(),
+        };
+        // Crates that were rendered successfully by the previous run
+        let previous: HashMap<String, ManifestEntry> = match &self.since {
+            Some(path) => read_manifest(path)
+                .await?
+                .crates
+                .into_iter()
+                .filter(|entry| entry.error.is_none())
+                .map(|entry| (entry.name.clone(), entry))
+                .collect(),
+            None => HashMap::new(),
+        };
+        let bucket_url = self
+            .out_folder
+            .to_str()
+            .and_then(|out| out.parse::<BucketUrl>().ok());
+        let archive_format = ArchiveFormat::detect(&self.out_folder);
+        if self.resume && archive_format.is_some() {
+            return Err(anyhow::anyhow!(
+                "Archives can't be resumed, as they're written from scratch"
+            )
+            .into());
+        }
+        // Archives and buckets are always written from scratch
+        let to_folder = bucket_url.is_none() && archive_format.is_none();
+        let skip_existing = self.skip_existing && !self.force && to_folder;
+        if self.post_cmd.is_some() && !to_folder {
+            return Err(anyhow::anyhow!("--post-cmd can only be used with output folders").into());
+        }
+        if self.verify && !to_folder {
+            return Err(anyhow::anyhow!("Only output folders can be verified").into());
+        }
+        // Dry runs don't touch the output at all
+        let output = match self.dry_run || self.verify {
+            true => None,
+            false => Some(Arc::new(match (bucket_url, archive_format) {
+                (Some(url), _) => Output::Bucket(Bucket::from_env(
+                    url,
+                    BucketOptions {
+                        endpoint: self.bucket_endpoint.clone(),
+                        region: self.bucket_region.clone(),
+                        concurrency: self.upload_concurrency,
+                        cache_control: self.cache_control.clone(),
+                    },
+                )?),
+                (None, Some(format)) => {
+                    Output::Archive(ArchiveWriter::create(&self.out_folder, format, self.force)?)
+                }
+                (None, None) => {
+                    tokio::fs::create_dir_all(&self.out_folder).await?;
+                    Output::Folder(self.out_folder.clone())
+                }
+            })),
+        };
+        let mut skipped = 0;
+        if let (Some(shard), LoadFilter::Select(items)) = (self.shard, &mut filter) {
+            items.retain(|_, name| shard.contains(name));
+        }
+        // Without crate data, the paths of crates' outputs are only known if they don't contain the version
+        let versioned = self
+            .out_pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.uses("version"));
+        if skip_existing && !versioned {
+            // Listed crates don't even need to be loaded if they're already rendered
+            if let LoadFilter::Select(items) = &mut filter {
+                let listed = items.len();
+                items.retain(|_, name| !self.is_rendered(&out_name(name), None));
+                skipped += listed - items.len();
+            }
+        }
 
-        // Add backpressure so we don't open too many files at once.
-        // 1000 should be on the safe side
-        let semaphore = Arc::new(Semaphore::new(1000));
-        // Rate limiter so we don't go fetch images from GitHub too often.
-        let mut rate_limit_ticker =
-            tokio::time::interval(Duration::from_micros(1000000 / self.rate));
+        // Rendering is CPU-bound, so don't compile more images at once than there are cores
+        let jobs = self.jobs.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        });
+        let render_slots = Arc::new(Semaphore::new(jobs.max(1)));
+        // Post-processing images is usually CPU-bound as well
+        let post_cmd_slots = Arc::new(Semaphore::new(jobs.max(1)));
+        // Rate limit fetching avatars, so we don't get rate limited by GitHub.
+        // Rendering crates whose avatars are cached can go at full speed.
+        convert::set_avatar_rate_limit(self.rate)?;
 
-        let db = Arc::new(CrateDb::preload_many(common.db_dump_path, items).await?);
+        let listed: Vec<String> = match &filter {
+            LoadFilter::Select(items) => items.values().cloned().collect(),
+            _ => vec![],
+        };
+        let (db, mut crates) = load_crates(&common, filter, &data_filter, self.top).await?;
+        let found = crates
+            .iter()
+            .filter(|pending| !matches!(pending, PendingCrate::Missing(_)))
+            .count();
+        if found == 0 && !listed.is_empty() && skipped == 0 {
+            eprintln!("❌ None of the {} listed crates were found", listed.len());
+            return Err(Error::NotFound);
+        }
+        if let Some(shard) = self.shard {
+            crates.retain(|pending| shard.contains(pending.name()));
+        }
+        let augment_crate = |pending: &PendingCrate| augment(&db, pending, &records);
+        if skip_existing {
+            let loaded = crates.len();
+            if versioned {
+                retain_augmented(&mut crates, augment_crate, |data| {
+                    !self.is_rendered(&out_name(data.name.as_ref()), data.version.as_deref())
+                });
+            } else {
+                crates.retain(|pending| !self.is_rendered(&out_name(pending.name()), None));
+            }
+            skipped += loaded - crates.len();
+        }
+        if skipped > 0 {
+            println!("⏭️ Skipping {skipped} crates that were already rendered");
+        }
+        // Crates that aren't rendered again, but carried over into the manifest
+        let mut carried = vec![];
+        if !resumed.is_empty() {
+            crates.retain(|pending| match resumed.get(pending.name()) {
+                Some(entry) => {
+                    carried.push(entry.clone());
+                    false
+                }
+                None => true,
+            });
+            println!(
+                "⏭️ Resuming, skipping {} crates that were finished before",
+                carried.len()
+            );
+        }
+        if !previous.is_empty() {
+            let unchanged = retain_augmented(&mut crates, augment_crate, |data| {
+                !previous.get(data.name.as_ref()).is_some_and(|entry| {
+                    entry.data_hash.as_deref()
+                        == Some(&data_hash(data, theme(data.name.as_ref()).as_deref()))
+                })
+            });
+            println!(
+                "⏭️ Skipping {} crates that didn't change since the previous run",
+                unchanged.len()
+            );
+            carried.extend(
+                unchanged
+                    .iter()
+                    .filter_map(|pending| previous.get(pending.name()).cloned()),
+            );
+        }
+        let template_hash = format!("{:x}", Sha256::digest(template::template_source()));
+        // The digest written to the `.hash` sidecars of a crate's images
+        let sidecar_hash = |data: &CrateData| {
+            sidecar_digest(
+                &data_hash(data, theme(data.name.as_ref()).as_deref()),
+                &template_hash,
+            )
+        };
+        if self.verify {
+            let pattern = self.out_pattern.as_ref();
+            let out_folder = &self.out_folder;
+            let verified: Vec<_> = tokio::task::block_in_place(|| {
+                crates
+                    .par_iter()
+                    .filter_map(|pending| {
+                        augment_crate(pending)
+                            .inspect_err(|e| {
+                                eprintln!(
+                                    "Skipping crate '{}' that could not be augmented: {e}",
+                                    pending.name()
+                                )
+                            })
+                            .ok()
+                    })
+                    .flat_map_iter(|data| {
+                        let out_name = out_name(data.name.as_ref());
+                        let hash = self.hash_sidecars.then(|| sidecar_hash(&data));
+                        self.sizes
+                            .iter()
+                            .map(|&size| {
+                                let file_name = image_file_name(
+                                    pattern,
+                                    &out_name,
+                                    data.version.as_deref(),
+                                    size,
+                                );
+                                let problem = verify_image(
+                                    &out_folder.join(&file_name),
+                                    size,
+                                    hash.as_deref(),
+                                )
+                                .err();
+                                (file_name, problem)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            });
+            let count = verified.len();
+            let mut problems: Vec<_> = verified
+                .into_iter()
+                .filter_map(|(file_name, problem)| Some((file_name, problem?)))
+                .collect();
+            problems.sort_unstable();
+            println!(
+                "🔍 Verified {count} images, {} have problems",
+                problems.len()
+            );
+            for (file_name, problem) in &problems {
+                println!("  {file_name}: {problem}");
+            }
+            if !problems.is_empty() {
+                return Err(
+                    anyhow::anyhow!("{} of {count} images have problems", problems.len()).into(),
+                );
+            }
+            return Ok(());
+        }
+        let mut up_to_date = 0;
+        if self.hash_sidecars && to_folder {
+            up_to_date = retain_augmented(&mut crates, augment_crate, |data| {
+                !self.sidecars_match(
+                    &out_name(data.name.as_ref()),
+                    data.version.as_deref(),
+                    &sidecar_hash(data),
+                )
+            })
+            .len();
+            println!("⏭️ Skipping {up_to_date} crates whose images are up to date");
+        }
+        let skipped = skipped + carried.len() + up_to_date;
+        let Some(output) = output else {
+            // Listed crates that weren't found are reported below
+            crates.retain(|pending| !matches!(pending, PendingCrate::Missing(_)));
+            let mut planned: Vec<_> = tokio::task::block_in_place(|| {
+                crates
+                    .par_iter()
+                    .filter_map(|pending| {
+                        let data = augment_crate(pending)
+                            .inspect_err(|e| {
+                                eprintln!(
+                                    "Skipping crate '{}' that could not be augmented: {e}",
+                                    pending.name()
+                                )
+                            })
+                            .ok()?;
+                        let files = self.output_file_names(
+                            &out_name(data.name.as_ref()),
+                            data.version.as_deref(),
+                        );
+                        Some((data.name.into_inner(), files))
+                    })
+                    .collect()
+            });
+            if self.top.is_none() {
+                planned.sort_unstable();
+            }
+            println!(
+                "📝 Would render {} crates to {}:",
+                planned.len(),
+                self.out_folder.display()
+            );
+            for (name, files) in &planned {
+                println!("  {name}: {}", files.join(", "));
+            }
+            let found: HashSet<String> = crates
+                .iter()
+                .map(PendingCrate::name)
+                .chain(carried.iter().map(|entry| entry.name.as_str()))
+                .map(normalize_crate_name)
+                .collect();
+            let mut missing: Vec<_> = listed
+                .iter()
+                .filter(|name| !found.contains(&normalize_crate_name(name)))
+                .collect();
+            if !missing.is_empty() {
+                missing.sort_unstable();
+                println!(
+                    "⚠️ {} listed crates were not found, or were filtered out:",
+                    missing.len()
+                );
+                for name in missing {
+                    println!("  {name}");
+                }
+            }
+            return Ok(());
+        };
+        let checkpoint = match &self.checkpoint {
+            Some(path) => Some(Arc::new(Checkpoint::open(path, self.resume).await?)),
+            None => None,
+        };
 
+        let progress = if self.quiet {
+            ProgressBar::hidden()
+        } else {
+            render_progress_bar(crates.len() as u64)
+        };
+        let failures = Arc::new(AtomicU64::new(0));
         let mut tasks = tokio::task::JoinSet::new();
-        for data in db.augment_preloaded() {
-            rate_limit_ticker.tick().await;
-            let permit = semaphore.clone().acquire_owned().await.unwrap();
-            let image_file_name = format!("{}.png", data.name);
-            let path = self.out_folder.join(image_file_name);
+        // Only keep a bounded number of crates in flight, so memory stays flat and
+        // few files are open regardless of the input size. Each crate is only
+        // augmented within its task. Some more crates are in flight than can be
+        // rendered at once, so writing the outputs overlaps with rendering.
+        let max_in_flight = jobs.max(1) * 4;
+        let mut finished = vec![];
+        let total = crates.len();
+        for pending in crates {
+            while tasks.len() >= max_in_flight {
+                finished.push(tasks.join_next().await.unwrap().unwrap());
+            }
+            if !self.keep_going && failures.load(Ordering::Relaxed) > 0 {
+                break;
+            }
+            let out_name = out_name(pending.name());
+            let options = RenderOptions {
+                theme: theme(pending.name()),
+                ..Default::default()
+            };
+            let db = db.clone();
+            let records = records.clone();
+            let template_hash = template_hash.clone();
+            let hash_sidecars = self.hash_sidecars;
+            let output = output.clone();
+            let render_slots = render_slots.clone();
+            let sizes = self.sizes.clone();
+            let alt_text = self.alt_text;
+            let with_metadata = self.with_metadata;
+            let post_cmd = self.post_cmd.clone();
+            let post_cmd_slots = post_cmd_slots.clone();
+            let out_folder = self.out_folder.clone();
+            let out_pattern = self.out_pattern.clone();
+            let progress = progress.clone();
+            let failures = failures.clone();
+            let checkpoint = checkpoint.clone();
             tasks.spawn(async move {
-                println!("🖼️  Generating image for crate '{}'", data.name);
-                // Move the permit to this task, so it only gets dropped
-                // once the task ends
-                let _permit = permit;
-                let png = data.render_as_png().await;
-                let mut file = if self.force {
-                    tokio::fs::File::create(path).await?
-                } else {
-                    tokio::fs::File::create_new(path).await?
+                let start = Instant::now();
+                let name = pending.name().to_string();
+                let mut outputs = vec![];
+                let mut hash = None;
+                let result = match augment(&db, &pending, &records) {
+                    Err(e) => Err(e),
+                    Ok(data) => {
+                        let data_hash = data_hash(&data, options.theme.as_deref());
+                        let sidecar_hash =
+                            hash_sidecars.then(|| sidecar_digest(&data_hash, &template_hash));
+                        hash = Some(data_hash);
+                        let mut backoff = self.retry_backoff;
+                        let mut attempt = 0;
+                        loop {
+                            let render = async {
+                                let alt_text = alt_text.then(|| data.alt_text());
+                                let metadata = match with_metadata {
+                                    true => Some(
+                                        serde_json::to_vec_pretty(&data)
+                                            .map_err(anyhow::Error::from)?,
+                                    ),
+                                    false => None,
+                                };
+                                // Held until compilation is done, even if it exceeds the deadline
+                                let render_slot =
+                                    render_slots.clone().acquire_owned().await.unwrap();
+                                let images = data
+                                    .clone()
+                                    .render_all_sizes_holding(
+                                        sizes.clone(),
+                                        options.clone(),
+                                        render_slot,
+                                    )
+                                    .await?;
+                                let images: Vec<_> = images
+                                    .into_iter()
+                                    .map(|(size, png)| {
+                                        let version = data.version.as_deref();
+                                        let path = image_file_name(
+                                            out_pattern.as_ref(),
+                                            &out_name,
+                                            version,
+                                            size,
+                                        );
+                                        (path, png)
+                                    })
+                                    .collect();
+                                // Sidecars come last, so they only exist if their image was written
+                                let sidecars: Vec<_> = sidecar_hash
+                                    .iter()
+                                    .flat_map(|hash| {
+                                        images.iter().map(move |(file_name, _)| {
+                                            (format!("{file_name}.hash"), hash.clone().into_bytes())
+                                        })
+                                    })
+                                    .collect();
+                                let image_count = images.len();
+                                let files = images
+                                    .into_iter()
+                                    .chain(alt_text.map(|alt_text| {
+                                        let path = companion_file_name(
+                                            out_pattern.as_ref(),
+                                            &out_name,
+                                            data.version.as_deref(),
+                                            "txt",
+                                        );
+                                        (path, alt_text.into())
+                                    }))
+                                    .chain(metadata.map(|metadata| {
+                                        let path = companion_file_name(
+                                            out_pattern.as_ref(),
+                                            &out_name,
+                                            data.version.as_deref(),
+                                            "json",
+                                        );
+                                        (path, metadata)
+                                    }))
+                                    .chain(sidecars);
+                                for (i, (file_name, contents)) in files.enumerate() {
+                                    // Files written by an earlier attempt are kept
+                                    if outputs.iter().any(|o: &ManifestOutput| o.path == file_name)
+                                    {
+                                        continue;
+                                    }
+                                    let mut sha256 = format!("{:x}", Sha256::digest(&contents));
+                                    let mut bytes = contents.len() as u64;
+                                    output.write(&file_name, contents, self.force).await?;
+                                    let mut post_cmd_error = None;
+                                    if let (Some(post_cmd), true) = (&post_cmd, i < image_count) {
+                                        let path = out_folder.join(&file_name);
+                                        let _slot = post_cmd_slots.acquire().await.unwrap();
+                                        match run_post_cmd(post_cmd, &path).await {
+                                            // The command may have changed the image
+                                            Ok(()) => {
+                                                let contents = tokio::fs::read(&path).await?;
+                                                sha256 = format!("{:x}", Sha256::digest(&contents));
+                                                bytes = contents.len() as u64;
+                                            }
+                                            Err(e) => {
+                                                progress.suspend(|| {
+                                                    eprintln!(
+                                                        "Post-processing {file_name} failed: {e}"
+                                                    )
+                                                });
+                                                post_cmd_error = Some(e);
+                                            }
+                                        }
+                                    }
+                                    outputs.push(ManifestOutput {
+                                        path: file_name,
+                                        sha256,
+                                        bytes,
+                                        post_cmd_error,
+                                    });
+                                }
+                                Ok::<_, Error>(())
+                            };
+                            // Catch panics while rendering, so they're reported like other failures
+                            let result = AssertUnwindSafe(render)
+                                .catch_unwind()
+                                .await
+                                .unwrap_or_else(|panic| Err(render_panicked(panic)));
+                            match result {
+                                Err(e) if attempt < self.retries => {
+                                    attempt += 1;
+                                    progress.suspend(|| {
+                                        eprintln!(
+                                            "Retrying crate '{name}' in {backoff:?} \
+                                        ({attempt}/{}): {e}",
+                                            self.retries
+                                        )
+                                    });
+                                    tokio::time::sleep(backoff).await;
+                                    backoff *= 2;
+                                }
+                                result => break result,
+                            }
+                        }
+                    }
                 };
+                if let Err(e) = &result {
+                    let failures = failures.fetch_add(1, Ordering::Relaxed) + 1;
+                    progress.set_message(format!("{failures} failed"));
+                    progress
+                        .suspend(|| eprintln!("Error generating image for crate '{name}': {e}"));
+                }
+                progress.inc(1);
+                let entry = ManifestEntry {
+                    name,
+                    outputs,
+                    duration_ms: start.elapsed().as_millis() as u64,
+                    data_hash: hash,
+                    error: result.as_ref().err().map(ToString::to_string),
+                };
+                if let Some(checkpoint) = checkpoint {
+                    if let Err(e) = checkpoint.record(&entry).await {
+                        progress.suspend(|| eprintln!("Error writing checkpoint: {e}"));
+                    }
+                }
+                (entry, result)
+            });
+        }
+
+        finished.extend(tasks.join_all().await);
+        let (mut crates, results): (Vec<_>, Vec<_>) = finished.into_iter().unzip();
+        progress.finish();
+        let summary = Summary::new(&crates, total + skipped, skipped, start.elapsed());
+        summary.print();
+        crates.extend(carried);
+        crates.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        let manifest = Manifest {
+            summary,
+            crates,
+            rejected_input,
+        };
+        let json = serde_json::to_vec_pretty(&manifest).map_err(anyhow::Error::from)?;
+        output.write(MANIFEST_FILE, json, true).await?;
+        let crates = manifest.crates;
+        if let Some(gallery) = &self.gallery {
+            output
+                .write(gallery, gallery_html(&crates, gallery).into_bytes(), true)
+                .await?;
+        }
+        if let Output::Archive(archive) = Arc::into_inner(output).unwrap() {
+            archive.finish().await?;
+        }
+        let failed: Vec<_> = crates
+            .iter()
+            .filter_map(|entry| Some((&entry.name, entry.error.as_ref()?)))
+            .collect();
+        if failed.is_empty() {
+            return Ok(());
+        }
+        if !self.keep_going {
+            return results.into_iter().collect();
+        }
+        eprintln!("❌ {} of {total} crates failed:", failed.len());
+        for (name, error) in &failed {
+            eprintln!("  {name}: {error}");
+        }
+        if results
+            .iter()
+            .all(|result| matches!(result, Ok(()) | Err(Error::NotFound)))
+        {
+            return Err(Error::NotFound);
+        }
+        Err(anyhow::anyhow!("{} of {total} crates failed", failed.len()).into())
+    }
+}
+
+/// Turn the payload of a panic while rendering into an error
+fn render_panicked(panic: Box<dyn Any + Send>) -> Error {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    anyhow::anyhow!("Rendering panicked: {message}").into()
+}
+
+/// Where the outputs of a bulk run are written
+// There's only ever one output, so its size doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum Output {
+    Folder(PathBuf),
+    Archive(ArchiveWriter),
+    Bucket(Bucket),
+}
+
+impl Output {
+    /// Write a file called `file_name` to the output, only
+    /// overwriting existing files in folders if `force` is set.
+    async fn write(&self, file_name: &str, contents: Vec<u8>, force: bool) -> Result<(), Error> {
+        match self {
+            Output::Folder(folder) => {
+                let path = folder.join(file_name);
+                if let Some(parent) = path.parent().filter(|_| file_name.contains('/')) {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                write_output(&path, &contents, force).await
+            }
+            Output::Archive(archive) => archive.add(file_name.to_string(), contents).await,
+            Output::Bucket(bucket) => bucket.put(file_name, contents).await,
+        }
+    }
+}
+
+/// The name of the file in the output folder listing what a bulk run did
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Report of a bulk run, for CI pipelines that publish the images
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    /// Totals of the run. Missing from the manifests of older versions
+    #[serde(default)]
+    summary: Summary,
+    /// Every crate the run tried to render, sorted by name
+    crates: Vec<ManifestEntry>,
+    /// Lines of input that were skipped, as they were invalid or duplicates
+    #[serde(default)]
+    rejected_input: Vec<RejectedInput>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ManifestEntry {
+    name: String,
+    /// The files written for the crate
+    outputs: Vec<ManifestOutput>,
+    /// How long rendering and writing the outputs took
+    duration_ms: u64,
+    /// The digest of the data the crate was rendered from, see [`data_hash`].
+    /// Missing from the manifests of older versions
+    #[serde(default)]
+    data_hash: Option<String>,
+    /// Why the crate failed, if it did
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct ManifestOutput {
+    /// The path of the file, relative to the output folder
+    path: String,
+    /// The hex-encoded SHA-256 digest of the file's contents
+    sha256: String,
+    /// The size of the file
+    #[serde(default)]
+    bytes: u64,
+    /// Why the `--post-cmd` failed for the file, if it did
+    #[serde(default)]
+    post_cmd_error: Option<String>,
+}
+
+/// Totals of a bulk run, to track regressions between template or dump versions
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct Summary {
+    /// The crates selected for the run, including skipped ones
+    requested: usize,
+    rendered: usize,
+    /// Crates that were already rendered, unchanged or up to date
+    skipped: usize,
+    failed: usize,
+    /// The size of all files written for the rendered crates
+    bytes_written: u64,
+    /// The wall-clock time of the whole run, including loading the data
+    duration_ms: u64,
+    /// Statistics of how long rendering and writing a crate took
+    render_ms_avg: u64,
+    render_ms_p50: u64,
+    render_ms_p95: u64,
+    render_ms_max: u64,
+}
+
+impl Summary {
+    /// Summarize the crates rendered by this run, out of `requested` crates
+    fn new(
+        rendered: &[ManifestEntry],
+        requested: usize,
+        skipped: usize,
+        duration: Duration,
+    ) -> Self {
+        let mut durations: Vec<u64> = rendered
+            .iter()
+            .filter(|entry| entry.error.is_none())
+            .map(|entry| entry.duration_ms)
+            .collect();
+        durations.sort_unstable();
+        let percentile = |p: f64| {
+            let index = ((durations.len().saturating_sub(1)) as f64 * p).round() as usize;
+            durations.get(index).copied().unwrap_or_default()
+        };
+        Self {
+            requested,
+            rendered: durations.len(),
+            skipped,
+            failed: rendered.len() - durations.len(),
+            bytes_written: rendered
+                .iter()
+                .flat_map(|entry| &entry.outputs)
+                .map(|output| output.bytes)
+                .sum(),
+            duration_ms: duration.as_millis() as u64,
+            render_ms_avg: durations.iter().sum::<u64>() / (durations.len().max(1) as u64),
+            render_ms_p50: percentile(0.5),
+            render_ms_p95: percentile(0.95),
+            render_ms_max: durations.last().copied().unwrap_or_default(),
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "📊 Rendered {} of {} crates ({} skipped, {} failed), wrote {:.1} MB in {:.1}s",
+            self.rendered,
+            self.requested,
+            self.skipped,
+            self.failed,
+            self.bytes_written as f64 / 1_000_000.0,
+            self.duration_ms as f64 / 1000.0,
+        );
+        if self.rendered > 0 {
+            println!(
+                "⏱️ Per crate: avg {}ms, p50 {}ms, p95 {}ms, max {}ms",
+                self.render_ms_avg, self.render_ms_p50, self.render_ms_p95, self.render_ms_max
+            );
+        }
+    }
+}
+
+/// A static HTML page showing the PNGs of `crates` in a grid, to be written to `path`
+fn gallery_html(crates: &[ManifestEntry], path: &str) -> String {
+    // Image paths are relative to the output, so go up to it from the gallery
+    let root = "../".repeat(path.matches('/').count());
+    let cards: String = crates
+        .iter()
+        .flat_map(|entry| {
+            let root = &root;
+            entry
+                .outputs
+                .iter()
+                .filter(|output| output.path.ends_with(".png"))
+                .map(move |output| {
+                    let name = html_escape(&entry.name);
+                    let src = html_escape(&format!("{root}{}", output.path));
+                    format!(
+                        "<figure><a href=\"{src}\"><img src=\"{src}\" alt=\"{name}\" loading=\"lazy\"></a>\
+                        <figcaption><a href=\"https://crates.io/crates/{name}\">{name}</a> \
+                        <small>{}</small></figcaption></figure>\n",
+                        html_escape(&output.path)
+                    )
+                })
+        })
+        .collect();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>OG Loc gallery</title>
+<style>
+body {{ font-family: sans-serif; margin: 2rem; }}
+main {{ display: grid; grid-template-columns: repeat(auto-fill, minmax(320px, 1fr)); gap: 1.5rem; }}
+figure {{ margin: 0; }}
+img {{ width: 100%; border: 1px solid #ddd; }}
+</style>
+</head>
+<body>
+<main>
+{cards}</main>
+</body>
+</html>
+"#
+    )
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Read the manifest written by a previous bulk run
+async fn read_manifest(path: &Path) -> Result<Manifest, Error> {
+    let contents = tokio::fs::read(path).await?;
+    let manifest = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow::anyhow!("Invalid manifest {}: {e}", path.display()))?;
+    Ok(manifest)
+}
+
+/// Run the `--post-cmd` for the image at `path`, returning why it failed if it did
+async fn run_post_cmd(post_cmd: &str, path: &Path) -> Result<(), String> {
+    let command = post_cmd.replace("{path}", &shell_quote(&path.to_string_lossy()));
+    let output = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .map_err(|e| format!("Could not run `{command}`: {e}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(format!(
+        "`{command}` failed with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// Quote `s` so the shell passes it as a single argument
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Check that the image at `path` is a PNG of the passed size, and, if `hash`
+/// is set, that its `.hash` sidecar matches it. Returns the problem if not.
+fn verify_image(path: &Path, size: ImageSize, hash: Option<&str>) -> Result<(), String> {
+    let contents = match std::fs::read(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err("missing".into()),
+        contents => contents.map_err(|e| format!("unreadable: {e}"))?,
+    };
+    let pixmap = tiny_skia::Pixmap::decode_png(&contents).map_err(|e| format!("corrupt: {e}"))?;
+    let actual = (pixmap.width(), pixmap.height());
+    // The size of `og` images depends on the template
+    if let Some(expected) = size.dimensions().filter(|&expected| expected != actual) {
+        return Err(format!(
+            "{}x{} pixels instead of {}x{}",
+            actual.0, actual.1, expected.0, expected.1
+        ));
+    }
+    if let Some(hash) = hash {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".hash");
+        if !std::fs::read_to_string(sidecar).is_ok_and(|contents| contents == hash) {
+            return Err("stale".into());
+        }
+    }
+    Ok(())
+}
+
+/// Records each crate a bulk run finished as a line of JSON, so the run can be resumed
+struct Checkpoint(tokio::sync::Mutex<File>);
+
+impl Checkpoint {
+    /// Open the checkpoint at `path`, appending to it if `resume` is set
+    async fn open(path: &Path, resume: bool) -> Result<Self, Error> {
+        let mut options = tokio::fs::OpenOptions::new();
+        match resume {
+            true => options.create(true).append(true),
+            false => options.create(true).write(true).truncate(true),
+        };
+        Ok(Self(tokio::sync::Mutex::new(options.open(path).await?)))
+    }
+
+    async fn record(&self, entry: &ManifestEntry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(entry).map_err(anyhow::Error::from)?;
+        line.push(b'\n');
+        // Write whole lines at once, so a crash leaves at most the last one incomplete
+        let mut file = self.0.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+/// Read the crates that were finished successfully according to the checkpoint
+/// at `path`. Incomplete lines are ignored, and a missing checkpoint is empty.
+async fn read_checkpoint(path: &Path) -> Result<Vec<ManifestEntry>, Error> {
+    let contents = match tokio::fs::read_to_string(path).await {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        contents => contents?,
+    };
+    Ok(parse_checkpoint(&contents))
+}
+
+fn parse_checkpoint(contents: &str) -> Vec<ManifestEntry> {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<ManifestEntry>(line).ok())
+        .filter(|entry| entry.error.is_none())
+        .collect()
+}
+
+/// A digest of the data and theme a crate is rendered with. If it's the same as
+/// in the previous run, so are the crate's images, as long as the template is too.
+fn data_hash(data: &CrateData, theme: Option<&str>) -> String {
+    let json = serde_json::to_vec(&(data, theme)).expect("crate data is serializable");
+    format!("{:x}", Sha256::digest(json))
+}
+
+/// The digest written to the `.hash` sidecars of the images of a crate
+/// whose data hashes to `data_hash`, rendered with the template hashing to
+/// `template_hash`
+fn sidecar_digest(data_hash: &str, template_hash: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("{data_hash}\n{template_hash}"))
+    )
+}
+
+/// Augment `pending`, with the description from its input record, if any
+fn augment(
+    db: &CrateDb,
+    pending: &PendingCrate,
+    records: &HashMap<String, CrateRecord>,
+) -> Result<CrateData, Error> {
+    let mut data = pending.augment(db)?;
+    let record = records.get(&normalize_crate_name(data.name.as_ref()));
+    if let Some(description) = record.and_then(|record| record.description.clone()) {
+        data.description = truncate_description(description);
+    }
+    Ok(data)
+}
+
+/// Keep only the crates for which `keep` returns true when passed their data,
+/// returning the others. Crates are augmented in parallel, and their data
+/// dropped right away. Crates that can't be augmented are kept, so they're
+/// reported as failures when rendering them.
+fn retain_augmented(
+    crates: &mut Vec<PendingCrate>,
+    augment: impl Fn(&PendingCrate) -> Result<CrateData, Error> + Sync,
+    keep: impl Fn(&CrateData) -> bool + Sync,
+) -> Vec<PendingCrate> {
+    let keep: Vec<bool> = tokio::task::block_in_place(|| {
+        crates
+            .par_iter()
+            .map(|pending| augment(pending).map_or(true, |data| keep(&data)))
+            .collect()
+    });
+    let (kept, removed): (Vec<_>, Vec<_>) = std::mem::take(crates)
+        .into_iter()
+        .zip(keep)
+        .partition(|(_, keep)| *keep);
+    *crates = kept.into_iter().map(|(pending, _)| pending).collect();
+    removed.into_iter().map(|(pending, _)| pending).collect()
+}
+
+impl Bulk {
+    /// Whether all outputs called after `out_name` exist in the output folder
+    fn is_rendered(&self, out_name: &str, version: Option<&str>) -> bool {
+        self.output_file_names(out_name, version)
+            .into_iter()
+            .all(|file_name| self.out_folder.join(file_name).exists())
+    }
+
+    /// Whether all images called after `out_name` exist in the output
+    /// folder, with `.hash` sidecars containing `hash`
+    fn sidecars_match(&self, out_name: &str, version: Option<&str>, hash: &str) -> bool {
+        self.sizes.iter().all(|&size| {
+            let file_name = image_file_name(self.out_pattern.as_ref(), out_name, version, size);
+            let image = self.out_folder.join(file_name);
+            let mut sidecar = image.clone().into_os_string();
+            sidecar.push(".hash");
+            image.exists()
+                && std::fs::read_to_string(sidecar).is_ok_and(|contents| contents == hash)
+        })
+    }
+
+    /// The names of the files written for a crate, called after `out_name`
+    fn output_file_names(&self, out_name: &str, version: Option<&str>) -> Vec<String> {
+        let pattern = self.out_pattern.as_ref();
+        self.sizes
+            .iter()
+            .map(|&size| image_file_name(pattern, out_name, version, size))
+            .chain(
+                self.alt_text
+                    .then(|| companion_file_name(pattern, out_name, version, "txt")),
+            )
+            .chain(
+                self.with_metadata
+                    .then(|| companion_file_name(pattern, out_name, version, "json")),
+            )
+            .collect()
+    }
+}
+
+/// A crate listed in a JSON Lines or CSV input file, with optional
+/// fields to use instead of those derived from the database dump
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub(crate) struct CrateRecord {
+    name: CrateName,
+    /// The description to show instead of the crate's own
+    #[serde(default)]
+    description: Option<String>,
+    /// The theme to render with, passed to the template
+    #[serde(default)]
+    theme: Option<String>,
+    /// The name of the output files, without extension, instead of the crate name
+    #[serde(default)]
+    out: Option<String>,
+}
+
+/// Read the crates listed in a JSON Lines or CSV file, depending on its extension
+async fn read_records(path: &Path) -> Result<Vec<CrateRecord>, anyhow::Error> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Could not read {}: {e}", path.display()))?;
+    let csv = path.extension().is_some_and(|ext| ext == "csv");
+    parse_records(&contents, csv)
+        .map_err(|e| anyhow::anyhow!("Invalid input file {}: {e}", path.display()))
+}
+
+/// Parse the records in `contents`, as CSV with a header row if `csv`
+/// is set, and as one JSON object per line otherwise
+fn parse_records(contents: &str, csv: bool) -> Result<Vec<CrateRecord>, anyhow::Error> {
+    let records: Vec<CrateRecord> = if csv {
+        csv::Reader::from_reader(contents.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()?
+    } else {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| anyhow::anyhow!("line {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()?
+    };
+    for record in &records {
+        // Output names end up in paths, so they mustn't escape the output folder
+        if let Some(out) = &record.out {
+            if out.is_empty() || out.starts_with('.') || out.contains(['/', '\\']) {
+                anyhow::bail!("invalid output name '{out}' for crate '{}'", record.name);
+            }
+        }
+    }
+    Ok(records)
+}
 
-                file.write_all(&png).await?;
-                Ok::<_, Error>(())
+/// One of a number of disjoint parts of the crates to render
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// The 1-based index of this shard
+    index: u64,
+    count: u64,
+}
+
+impl Shard {
+    /// Whether the crate called `name` is in this shard. This only depends on
+    /// the name, so it's the same on every machine and for every input.
+    fn contains(&self, name: &str) -> bool {
+        // FNV-1a, as the hashers in std aren't guaranteed to be stable
+        let hash = normalize_crate_name(name)
+            .bytes()
+            .fold(0xcbf29ce484222325_u64, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
             });
+        hash % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard '{s}', expected `i/n` with 1 <= i <= n");
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: u64 = index.parse().map_err(|_| invalid())?;
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Self { index, count })
+    }
+}
+
+/// The crates to load from the database dump: those matching `regex` if
+/// given, or else those listed or matched by `input`, or else all of them.
+/// Invalid and duplicate names in the input are reported and skipped, along
+/// with the reason, unless `strict` is set, in which case invalid names fail.
+pub(crate) async fn load_filter(
+    input: Option<BulkInput>,
+    regex: Option<regex::Regex>,
+    strict: bool,
+) -> Result<(LoadFilter, Vec<RejectedInput>), Error> {
+    let filter = match (input, regex) {
+        (_, Some(regex)) => LoadFilter::Regex(regex),
+        (None, None) => LoadFilter::All,
+        (Some(BulkInput::Pattern(pattern)), _) => {
+            LoadFilter::from_pattern(&pattern).map_err(anyhow::Error::from)?
+        }
+        (Some(input), None) => {
+            let (items, rejected) = read_input(input.into_stream().await?, strict).await?;
+            for RejectedInput { input, reason } in &rejected {
+                eprintln!("Skipping input '{input}': {reason}");
+            }
+            return Ok((LoadFilter::select(items), rejected));
+        }
+    };
+    Ok((filter, vec![]))
+}
+
+/// A line of input that was skipped
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub(crate) struct RejectedInput {
+    input: String,
+    reason: String,
+}
+
+/// Collect the valid, distinct crate names in `names`. Blank lines are ignored.
+async fn read_input(
+    names: impl Stream<Item = Result<CrateName, BulkInputError>>,
+    strict: bool,
+) -> Result<(HashSet<String>, Vec<RejectedInput>), Error> {
+    let mut names = pin!(names);
+    let mut items = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut rejected = vec![];
+    while let Some(name) = names.next().await {
+        match name {
+            // `serde-json` and `serde_json` are the same crate
+            Ok(name) if !seen.insert(normalize_crate_name(name.as_ref())) => {
+                rejected.push(RejectedInput {
+                    input: name.into_inner(),
+                    reason: "Duplicate crate name".into(),
+                });
+            }
+            Ok(name) => {
+                items.insert(name.into_inner());
+            }
+            Err(BulkInputError::InvalidCrateName(line, _)) if line.trim().is_empty() => {}
+            Err(BulkInputError::InvalidCrateName(line, e)) if !strict => {
+                rejected.push(RejectedInput {
+                    input: line,
+                    reason: e.to_string(),
+                });
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok((items, rejected))
+}
+
+/// A crate to process, which is only augmented once it's needed,
+/// so memory stays flat regardless of the number of crates
+pub(crate) enum PendingCrate {
+    /// A crate in the database dump
+    Dump(Box<str>),
+    /// A crate missing from the database dump, fetched from the crates.io API
+    Fetched(Box<CrateData>),
+    /// A listed crate that wasn't found, which fails when it's augmented
+    Missing(String),
+    /// A listed crate that couldn't be fetched from the crates.io API, with the
+    /// error, which fails when it's augmented
+    Unfetchable(String, String),
+}
+
+impl PendingCrate {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            PendingCrate::Dump(name) => name,
+            PendingCrate::Fetched(data) => data.name.as_ref(),
+            PendingCrate::Missing(name) | PendingCrate::Unfetchable(name, _) => name,
+        }
+    }
+
+    fn downloads(&self, db: &CrateDb) -> u64 {
+        match self {
+            PendingCrate::Dump(name) => db.downloads(name).unwrap_or_default(),
+            PendingCrate::Fetched(data) => data.downloads,
+            PendingCrate::Missing(_) | PendingCrate::Unfetchable(..) => 0,
+        }
+    }
+
+    pub(crate) fn augment(&self, db: &CrateDb) -> Result<CrateData, Error> {
+        match self {
+            PendingCrate::Dump(name) => db.augment_crate_spec(name.parse()?),
+            PendingCrate::Fetched(data) => Ok((**data).clone()),
+            PendingCrate::Missing(_) => Err(Error::NotFound),
+            PendingCrate::Unfetchable(_, error) => {
+                Err(anyhow::anyhow!("Fetching the crate failed: {error}").into())
+            }
+        }
+    }
+}
+
+/// Load the crates matching both `filter` and `data_filter`, without augmenting
+/// them yet. Crates that are listed explicitly but missing from the database dump
+/// are fetched from the crates.io API, if enabled, or else [`PendingCrate::Missing`].
+/// Crates that can't be fetched are [`PendingCrate::Unfetchable`].
+/// If `top` is set, only that many crates are loaded: the most downloaded ones, in order.
+pub(crate) async fn load_crates(
+    common: &CommonArgs,
+    filter: LoadFilter,
+    data_filter: &DataFilter,
+    top: Option<usize>,
+) -> Result<(Arc<CrateDb>, Vec<PendingCrate>), Error> {
+    // Crates missing from the dump may still be fetched from the API
+    let missing = match &filter {
+        LoadFilter::Select(items) => items.values().cloned().collect(),
+        _ => vec![],
+    };
+    let mut db = common.preload(filter).await?;
+    let missing: Vec<_> = missing
+        .into_iter()
+        .filter(|name| !db.contains(name))
+        .collect();
+    db.retain_data(data_filter);
+    if let Some(n) = top {
+        db.retain_top_by_downloads(n);
+    }
+
+    let mut fetched = vec![];
+    for name in missing {
+        match db.augment_crate_spec_or_fetch(name.parse()?).await {
+            Ok(data) if data_filter.matches(&data) => {
+                fetched.push(PendingCrate::Fetched(Box::new(data)))
+            }
+            Ok(_) => {}
+            Err(Error::NotFound) => fetched.push(PendingCrate::Missing(name)),
+            // A crate that can't be fetched shouldn't stop loading the others
+            Err(e) => fetched.push(PendingCrate::Unfetchable(name, e.to_string())),
+        }
+    }
+
+    let mut crates: Vec<_> = db
+        .crate_names()
+        .map(|name| PendingCrate::Dump(name.into()))
+        .chain(fetched)
+        .collect();
+    if let Some(n) = top {
+        // Fetched crates may be more popular than the ones from the dump
+        crates.sort_unstable_by(|a, b| {
+            b.downloads(&db)
+                .cmp(&a.downloads(&db))
+                .then_with(|| a.name().cmp(b.name()))
+        });
+        crates.truncate(n);
+    }
+    Ok((Arc::new(db), crates))
+}
+
+/// Augment all of `crates`, using all cores. Crates that
+/// can't be augmented are reported and skipped.
+pub(crate) async fn augment_all(db: Arc<CrateDb>, crates: Vec<PendingCrate>) -> Vec<CrateData> {
+    tokio::task::spawn_blocking(move || {
+        crates
+            .par_iter()
+            .filter_map(|pending| {
+                pending
+                    .augment(&db)
+                    .inspect_err(|e| {
+                        eprintln!(
+                            "Skipping crate '{}' that could not be augmented: {e}",
+                            pending.name()
+                        )
+                    })
+                    .ok()
+            })
+            .collect()
+    })
+    .await
+    .unwrap()
+}
+
+/// A progress bar for rendering `total` crates, showing
+/// the render rate, the number of failures and the ETA
+fn render_progress_bar(total: u64) -> ProgressBar {
+    let progress = ProgressBar::with_draw_target(Some(total), ProgressDrawTarget::stderr());
+    progress.set_style(
+        ProgressStyle::with_template(
+            "[{wide_bar:.cyan/blue}] {pos}/{len} {per_sec:>10} ETA {eta:<4} {msg}",
+        )
+        .unwrap()
+        .progress_chars(". "),
+    );
+    progress
+}
+
+/// The name of the image file of the passed size for a crate,
+/// following `pattern` if set.
+fn image_file_name(
+    pattern: Option<&OutPattern>,
+    name: &str,
+    version: Option<&str>,
+    size: ImageSize,
+) -> String {
+    match (pattern, size) {
+        (Some(pattern), size) => pattern.file_name(name, version, size),
+        (None, ImageSize::Og) => format!("{name}.png"),
+        (None, size) => format!("{name}-{size}.png"),
+    }
+}
+
+/// The name of a file with the passed extension next to
+/// the `og` image of a crate, like its alternative text
+fn companion_file_name(
+    pattern: Option<&OutPattern>,
+    name: &str,
+    version: Option<&str>,
+    extension: &str,
+) -> String {
+    match pattern {
+        Some(pattern) => format!("{}.{extension}", pattern.stem(name, version, ImageSize::Og)),
+        None => format!("{name}.{extension}"),
+    }
+}
+
+/// A pattern for the paths of the images written for each crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutPattern(String);
+
+impl OutPattern {
+    /// The placeholders patterns may contain
+    const PLACEHOLDERS: [&str; 5] = ["name", "first1", "first2", "version", "size"];
+
+    /// Whether the pattern contains `{placeholder}`
+    fn uses(&self, placeholder: &str) -> bool {
+        self.0.contains(&format!("{{{placeholder}}}"))
+    }
+
+    /// The path of the image of the passed size for a crate
+    fn file_name(&self, name: &str, version: Option<&str>, size: ImageSize) -> String {
+        let first = |n: usize| name.chars().take(n).collect::<String>().to_lowercase();
+        self.0
+            .replace("{name}", name)
+            .replace("{first1}", &first(1))
+            .replace("{first2}", &first(2))
+            .replace("{version}", version.unwrap_or("unknown"))
+            .replace("{size}", &size.to_string())
+    }
+
+    /// The path of the image of the passed size for a crate, without the
+    /// extension in the pattern. Versions may contain dots, so only the
+    /// extension in the pattern itself is stripped.
+    fn stem(&self, name: &str, version: Option<&str>, size: ImageSize) -> String {
+        let mut file_name = self.file_name(name, version, size);
+        let last_segment = self.0.rsplit(['/', '\\']).next().unwrap_or_default();
+        if let Some((_, extension)) = last_segment.rsplit_once('.') {
+            if !extension.contains('}') {
+                file_name.truncate(file_name.len() - extension.len() - 1);
+            }
         }
+        file_name
+    }
+}
+
+impl FromStr for OutPattern {
+    type Err = String;
 
-        tasks.join_all().await.into_iter().collect()
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in '{s}'"))?;
+            let placeholder = &rest[start + 1..start + end];
+            if !Self::PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "Unknown placeholder {{{placeholder}}}, expected one of {}",
+                    Self::PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+        let pattern = Self(s.to_string());
+        if !pattern.uses("name") {
+            return Err(format!("Pattern '{s}' must contain {{name}}"));
+        }
+        // Patterns must not write outside of the output
+        if s.starts_with('/') || s.split(['/', '\\']).any(|segment| segment == "..") {
+            return Err(format!(
+                "Pattern '{s}' must be a relative path within the output"
+            ));
+        }
+        Ok(pattern)
     }
 }
 
+/// Write the contents to the file at the path, only overwriting existing files
+/// if `force` is set. The contents are written to a temporary file next to it,
+/// which is then renamed, so that readers like a server serving the same folder
+/// never see a partially written file, even if writing is interrupted. Without
+/// `force`, the temporary file is hard linked instead, which fails if the file
+/// exists, even if it was created by someone else while writing.
+pub(crate) async fn write_output(path: &Path, contents: &[u8], force: bool) -> Result<(), Error> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a file", path.display()),
+        )
+    })?;
+    // Hidden, so the temporary file isn't picked up by anything listing the folder
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{:016x}.tmp", fastrand::u64(..)));
+    let temp_path = path.with_file_name(temp_name);
+    let result = async {
+        tokio::fs::write(&temp_path, contents).await?;
+        if force {
+            return tokio::fs::rename(&temp_path, path).await;
+        }
+        tokio::fs::hard_link(&temp_path, path)
+            .await
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::AlreadyExists => io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists", path.display()),
+                ),
+                _ => e,
+            })?;
+        tokio::fs::remove_file(&temp_path).await
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    Ok(result?)
+}
+
 #[derive(Clone, Default, Debug, Hash, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(try_from = "&str")]
 pub enum BulkInput {
     Path(PathBuf),
+    /// A JSON Lines or CSV file of crates, with optional per-crate overrides
+    Records(PathBuf),
     List(Vec<CrateName>),
+    /// A glob pattern matched against the crates in the database dump
+    Pattern(String),
     #[default]
     StdIn,
 }
@@ -91,8 +1633,9 @@ pub enum BulkInput {
 pub enum BulkInputError {
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
-    #[error("Invalid Crate name: {0}")]
-    InvalidCrateName(#[from] InvalidCrateName),
+    /// A line of input that isn't a valid crate name
+    #[error("{1} ('{0}')")]
+    InvalidCrateName(String, #[source] InvalidCrateName),
 }
 
 impl BulkInput {
@@ -118,8 +1661,10 @@ impl BulkInput {
                 ) -> Poll<Option<<BulkInputStream as Stream>::Item>> {
                     pin!(lines.next_line()).poll(cx).map(|line| {
                         line.transpose().map(|l| {
-                            l.map_err(Into::into)
-                                .and_then(|l| CrateName::from_str(&l).map_err(Into::into))
+                            l.map_err(Into::into).and_then(|l| {
+                                CrateName::from_str(&l)
+                                    .map_err(|e| BulkInputError::InvalidCrateName(l, e))
+                            })
                         })
                     })
                 }
@@ -139,7 +1684,22 @@ impl BulkInput {
             BulkInput::Path(path_buf) => {
                 BulkInputStream::Path(BufReader::new(File::open(path_buf).await?).lines())
             }
-            BulkInput::List(list) => BulkInputStream::List(stream::iter(list.into_iter())),
+            BulkInput::Records(path_buf) => {
+                let names = read_records(&path_buf)
+                    .await
+                    .map_err(io::Error::other)?
+                    .into_iter()
+                    .map(|record| record.name)
+                    .collect::<Vec<_>>();
+                BulkInputStream::List(stream::iter(names))
+            }
+            BulkInput::List(list) => BulkInputStream::List(stream::iter(list)),
+            BulkInput::Pattern(pattern) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Pattern '{pattern}' can't be read as a list of crate names"),
+                ))
+            }
             BulkInput::StdIn => BulkInputStream::StdIn(BufReader::new(stdin()).lines()),
         };
 
@@ -178,6 +1738,17 @@ impl FromStr for BulkInput {
             return Ok(Self::StdIn);
         };
 
+        if s.contains(['*', '?', '[']) && glob::Pattern::new(s).is_ok() {
+            return Ok(Self::Pattern(s.to_string()));
+        }
+
+        if Path::new(s)
+            .extension()
+            .is_some_and(|ext| ext == "jsonl" || ext == "csv")
+        {
+            return Ok(Self::Records(s.into()));
+        }
+
         if let Ok(list) = s.split(',').try_fold(vec![], |mut res, name| {
             res.push(name.parse()?);
             Ok::<_, <CrateName as FromStr>::Err>(res)
@@ -188,3 +1759,257 @@ impl FromStr for BulkInput {
         Ok(Self::Path(s.into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use test_case::case;
+
+    use futures_lite::stream;
+
+    use super::{
+        gallery_html, parse_checkpoint, parse_records, read_input, run_post_cmd, verify_image,
+        write_output, BulkInputError, CrateRecord, Manifest, ManifestEntry, ManifestOutput,
+        OutPattern, Shard, Summary,
+    };
+    use crate::convert::ImageSize;
+    use crate::spec::CrateName;
+
+    #[case("1/1" => Ok(Shard { index: 1, count: 1 }))]
+    #[case("2/4" => Ok(Shard { index: 2, count: 4 }))]
+    #[case("0/4" => matches Err(_))]
+    #[case("5/4" => matches Err(_))]
+    #[case("2" => matches Err(_))]
+    fn test_parse_shard(s: &str) -> Result<Shard, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn test_shards_partition_crates() {
+        let names = [
+            "serde", "tokio", "knien", "rand", "syn", "quote", "log", "anyhow",
+        ];
+        let shards: Vec<Shard> = (1..=3).map(|i| format!("{i}/3").parse().unwrap()).collect();
+        for name in names {
+            let containing = shards.iter().filter(|shard| shard.contains(name)).count();
+            assert_eq!(containing, 1, "{name} is in {containing} shards");
+        }
+        assert!(shards
+            .iter()
+            .all(|shard| shard.contains("Serde_Json") == shard.contains("serde-json")));
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let expected = vec![
+            CrateRecord {
+                name: "knien".parse().unwrap(),
+                description: Some("Typed RabbitMQ interfacing".into()),
+                theme: Some("dark".into()),
+                out: None,
+            },
+            CrateRecord {
+                name: "serde".parse().unwrap(),
+                description: None,
+                theme: None,
+                out: Some("serde-launch".into()),
+            },
+        ];
+        let jsonl = r#"{"name": "knien", "description": "Typed RabbitMQ interfacing", "theme": "dark"}
+
+{"name": "serde", "out": "serde-launch"}"#;
+        assert_eq!(parse_records(jsonl, false).unwrap(), expected);
+        let csv = "name,description,theme,out\n\
+            knien,Typed RabbitMQ interfacing,dark,\n\
+            serde,,,serde-launch\n";
+        assert_eq!(parse_records(csv, true).unwrap(), expected);
+
+        assert!(parse_records(r#"{"name": "knien", "out": "../knien"}"#, false).is_err());
+        assert!(parse_records(r#"{"description": "No name"}"#, false).is_err());
+    }
+
+    #[case("{first2}/{name}.png", ImageSize::Og => Ok("se/Serde.png".to_string()))]
+    #[case("{name}/{version}-{size}.webp", ImageSize::Square => Ok("Serde/1.0.0-square.webp".to_string()))]
+    #[case("{first1}/{name}", ImageSize::Og => Ok("s/Serde".to_string()))]
+    #[case("{crate}.png", ImageSize::Og => matches Err(_))]
+    #[case("{first2}.png", ImageSize::Og => matches Err(_))]
+    #[case("../{name}.png", ImageSize::Og => matches Err(_))]
+    #[case("images/{name.png", ImageSize::Og => matches Err(_))]
+    fn test_out_pattern(pattern: &str, size: ImageSize) -> Result<String, String> {
+        let pattern: OutPattern = pattern.parse()?;
+        Ok(pattern.file_name("Serde", Some("1.0.0"), size))
+    }
+
+    #[case("{first2}/{name}.png" => "se/serde")]
+    #[case("{name}-{version}" => "serde-1.0.0")]
+    #[case("{name}.{version}.png" => "serde.1.0.0")]
+    fn test_out_pattern_stem(pattern: &str) -> String {
+        let pattern: OutPattern = pattern.parse().unwrap();
+        pattern.stem("serde", Some("1.0.0"), ImageSize::Og)
+    }
+
+    #[test]
+    fn test_read_manifest_without_data_hash() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{"crates": [{"name": "knien", "outputs": [], "duration_ms": 12, "error": null}]}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.crates[0].name, "knien");
+        assert_eq!(manifest.crates[0].data_hash, None);
+    }
+
+    #[tokio::test]
+    async fn test_read_input() {
+        let lines = || {
+            ["serde", "", "not a crate", "tokio", "Serde"].map(|line| {
+                line.parse::<CrateName>()
+                    .map_err(|e| BulkInputError::InvalidCrateName(line.into(), e))
+            })
+        };
+        let (items, rejected) = read_input(stream::iter(lines()), false).await.unwrap();
+        assert_eq!(items, ["serde".to_string(), "tokio".to_string()].into());
+        let rejected: Vec<_> = rejected.into_iter().map(|r| r.input).collect();
+        assert_eq!(rejected, ["not a crate", "Serde"]);
+
+        assert!(read_input(stream::iter(lines()), true).await.is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let entry = |duration_ms, error: Option<&str>| ManifestEntry {
+            name: "knien".into(),
+            outputs: vec![ManifestOutput {
+                path: "knien.png".into(),
+                sha256: String::new(),
+                bytes: 1000,
+                post_cmd_error: None,
+            }],
+            duration_ms,
+            data_hash: None,
+            error: error.map(Into::into),
+        };
+        let mut rendered: Vec<_> = (1..=20).map(|i| entry(i * 10, None)).collect();
+        rendered.push(entry(5, Some("Avatar could not be fetched")));
+        let summary = Summary::new(&rendered, 25, 4, std::time::Duration::from_secs(2));
+        assert_eq!(
+            summary,
+            Summary {
+                requested: 25,
+                rendered: 20,
+                skipped: 4,
+                failed: 1,
+                bytes_written: 21_000,
+                duration_ms: 2000,
+                render_ms_avg: 105,
+                render_ms_p50: 110,
+                render_ms_p95: 190,
+                render_ms_max: 200,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_checkpoint() {
+        let checkpoint = r#"{"name": "knien", "outputs": [], "duration_ms": 12, "error": null}
+{"name": "serde", "outputs": [], "duration_ms": 3, "error": "Rendering panicked"}
+{"name": "tokio", "outputs": [], "dura"#;
+        let names: Vec<_> = parse_checkpoint(checkpoint)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["knien"]);
+    }
+
+    #[test]
+    fn test_verify_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = tiny_skia::Pixmap::new(1200, 600)
+            .unwrap()
+            .encode_png()
+            .unwrap();
+        std::fs::write(dir.path().join("knien-twitter.png"), &png).unwrap();
+        std::fs::write(dir.path().join("knien-twitter.png.hash"), "abc").unwrap();
+        std::fs::write(dir.path().join("corrupt.png"), b"not a png").unwrap();
+
+        let verify =
+            |file_name: &str, size, hash| verify_image(&dir.path().join(file_name), size, hash);
+        assert_eq!(
+            verify("knien-twitter.png", ImageSize::Twitter, Some("abc")),
+            Ok(())
+        );
+        assert_eq!(verify("knien-twitter.png", ImageSize::Og, None), Ok(()));
+        assert_eq!(
+            verify("knien-twitter.png", ImageSize::Square, None),
+            Err("1200x600 pixels instead of 1200x1200".into())
+        );
+        assert_eq!(
+            verify("knien-twitter.png", ImageSize::Twitter, Some("def")),
+            Err("stale".into())
+        );
+        assert!(verify("corrupt.png", ImageSize::Og, None)
+            .unwrap_err()
+            .starts_with("corrupt"));
+        assert_eq!(
+            verify("missing.png", ImageSize::Og, None),
+            Err("missing".into())
+        );
+    }
+
+    #[test]
+    fn test_gallery_html() {
+        let entry = ManifestEntry {
+            name: "knien".into(),
+            outputs: ["kn/knien.png", "kn/knien.txt"]
+                .map(|path| ManifestOutput {
+                    path: path.into(),
+                    sha256: String::new(),
+                    bytes: 0,
+                    post_cmd_error: None,
+                })
+                .into(),
+            duration_ms: 0,
+            data_hash: None,
+            error: None,
+        };
+        let html = gallery_html(&[entry], "review/index.html");
+        assert!(html.contains(r#"<img src="../kn/knien.png" alt="knien""#));
+        assert!(html.contains(r#"<a href="https://crates.io/crates/knien">knien</a>"#));
+        assert!(!html.contains("knien.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_run_post_cmd() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("it's here.png");
+        std::fs::write(&path, b"png").unwrap();
+
+        assert_eq!(run_post_cmd("printf x >> {path}", &path).await, Ok(()));
+        assert_eq!(std::fs::read(&path).unwrap(), b"pngx");
+        let error = run_post_cmd("echo oops >&2; exit 3", &path)
+            .await
+            .unwrap_err();
+        assert!(error.ends_with("oops"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_write_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("knien.png");
+
+        write_output(&path, b"first", false).await.unwrap();
+        assert!(write_output(&path, b"second", false).await.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        // Files that weren't written by a bulk run aren't overwritten either
+        let other = dir.path().join("other.png");
+        std::fs::write(&other, b"other").unwrap();
+        let error = write_output(&other, b"second", false).await.unwrap_err();
+        assert!(
+            matches!(error, crate::Error::Io(e) if e.kind() == std::io::ErrorKind::AlreadyExists)
+        );
+        assert_eq!(std::fs::read(&other).unwrap(), b"other");
+        std::fs::remove_file(other).unwrap();
+        write_output(&path, b"third", true).await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"third");
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
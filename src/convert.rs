@@ -1,10 +1,22 @@
-use std::sync::{Arc, LazyLock};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, OnceLock},
+    time::{Duration, Instant},
+};
 
-use aho_corasick::AhoCorasick;
-use minijinja::{context, Environment};
+use chrono::{DateTime, Utc};
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    ExtendedColorType, ImageEncoder,
+};
+use minijinja::context;
+use tiny_skia::{Color, Pixmap, PixmapPaint, Transform};
 use typst::{
-    diag::{FileError, FileResult, Warned},
+    diag::{FileError, FileResult, SourceDiagnostic, Warned},
     foundations::{Bytes, Datetime},
+    layout::Page,
+    model::Document,
     syntax::{FileId, Source, VirtualPath},
     text::{Font, FontBook},
     utils::LazyHash,
@@ -12,120 +24,736 @@ use typst::{
 };
 use typst_kit::fonts::{FontSlot, Fonts};
 
-use crate::{spec::CrateName, HTTP_CLIENT};
-
-/// Identifier for the Open Graph template in the
-/// [`minijinja::Environment`]
-const OG_TEMPLATE_NAME: &str = "og-typst";
+use crate::{error::Error, spec::CrateName, template, HTTP_CLIENT};
 
-/// Set up the [`minijinja::Environment`] for rendering the
-/// Jinja2 template to Typst source.
-static TEMPLATE_ENV: LazyLock<minijinja::Environment> = LazyLock::new(|| {
-    const OG_TEMPLATE_J2: &str = include_str!("../template.typ.j2");
-    let mut env = Environment::new();
-    env.add_template(OG_TEMPLATE_NAME, OG_TEMPLATE_J2).unwrap();
-    env
-});
-
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
 /// Crate data used for rendering the Jinja2 template
 /// to Typst source.
 pub struct CrateData {
     /// The name of the crate
     pub name: CrateName,
     /// The crate's description
-    pub description: TypstString,
+    pub description: String,
     /// The team owners of the crate
     pub team_owners: Vec<TeamCrateOwner>,
     /// The user owners of the crate
     pub user_owners: Vec<UserCrateOwner>,
+    /// Downloads per week over the last weeks, oldest first
+    pub weekly_downloads: Vec<u64>,
+    /// The license of the crate's default version, if known
+    pub license: Option<String>,
+    /// The version number of the crate's default version, e.g. `1.0.219`
+    pub version: Option<String>,
+    /// The minimum supported Rust version of the default version, if specified
+    pub rust_version: Option<String>,
+    /// The size of the default version's `.crate` file in bytes, if known
+    pub crate_size: Option<u64>,
+    /// When the default version was published
+    pub published_at: Option<DateTime<Utc>>,
+    /// The number of published versions, including yanked ones
+    pub num_versions: u32,
+    /// The total number of owners, which may be more than
+    /// the number of owners listed in `team_owners` and `user_owners`
+    pub num_owners: usize,
+    /// The all-time number of downloads
+    pub downloads: u64,
+    /// The number of downloads in the last 90 days
+    pub recent_downloads: u64,
+    /// The number of crates whose default version depends on this crate
+    pub dependents: u64,
+    /// Up to five of the crate's keywords, sorted alphabetically
+    pub keywords: Vec<String>,
+    /// The crate's categories, sorted by slug
+    pub categories: Vec<CrateCategory>,
+    /// The URL of the crate's source repository
+    pub repository: Option<String>,
+    /// The URL of the crate's homepage
+    pub homepage: Option<String>,
+    /// The URL of the crate's documentation
+    pub documentation: Option<String>,
+    /// When the crate was first published
+    pub created_at: DateTime<Utc>,
+    /// When the crate was last updated
+    pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
 /// A team crate owner
 pub struct TeamCrateOwner {
     /// URL of the owner's avatar image
-    pub avatar: TypstString,
+    pub avatar: String,
+    /// The team's `org/team` name, e.g. `rust-lang/libs`
+    pub name: String,
+    /// The GitHub organization the team belongs to, e.g. `rust-lang`
+    pub org: String,
+    /// URL of the team's page on GitHub
+    pub url: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+/// A crates.io category
+pub struct CrateCategory {
+    /// The category's display name, e.g. `Web programming::HTTP server`
+    pub name: String,
+    /// The category's slug, e.g. `web-programming::http-server`
+    pub slug: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
 /// A user crate owner
 pub struct UserCrateOwner {
     /// URL of the owner's avatar image
-    pub avatar: TypstString,
+    pub avatar: String,
+    /// The owner's GitHub login
+    pub login: String,
+    /// The owner's display name, if they set one
+    pub name: Option<String>,
+    /// URL of the owner's GitHub profile
+    pub url: String,
 }
 
-#[derive(Debug, Clone, serde::Serialize, PartialEq, Eq, Hash)]
-/// A string that is safe to use directly in typst source code.
-pub struct TypstString(String);
+impl CrateData {
+    /// A short textual description of the image, to be used
+    /// as alternative text, e.g. in `og:image:alt` tags.
+    pub fn alt_text(&self) -> String {
+        let mut alt = self.name.to_string();
+        let description = self.description.trim_end_matches(['.', '…']);
+        if !description.is_empty() {
+            alt.push_str(" — ");
+            alt.push_str(description);
+        }
+        alt.push_str(". ");
+        match self.num_owners {
+            1 => alt.push_str("1 owner"),
+            n => alt.push_str(&format!("{n} owners")),
+        }
+        if let Some(license) = &self.license {
+            alt.push_str(", ");
+            alt.push_str(license);
+        }
+        alt
+    }
+
+    /// The Typst source the template renders for this [`CrateData`], which is
+    /// then compiled into the image. Useful for debugging templates.
+    pub fn typst_source(&self, options: &RenderOptions) -> Result<String, Error> {
+        let now = if options.stable {
+            *STABLE_NOW
+        } else {
+            Utc::now()
+        };
+        self.render_as_typst_source(now, options.theme.as_deref())
+    }
 
-impl From<&str> for TypstString {
-    fn from(s: &str) -> Self {
-        const NUM_REPLACE_ITEMS: usize = 5;
-        const REPLACE: [(&str, &str); NUM_REPLACE_ITEMS] = [
-            // TODO figure out whether this list is exhaustive and correct
-            ("#", r"\#"),
-            (r"\", r"\\"),
-            ("^", r"\^"),
-            ("$", r"\$"),
-            (r#"""#, r#"\""#),
-        ];
+    fn render_as_typst_source(
+        &self,
+        now: DateTime<Utc>,
+        theme: Option<&str>,
+    ) -> Result<String, Error> {
+        Ok(template::render(self.template_context(now, theme))?)
+    }
 
-        // Alas, <[N;T]>::map is not const, so instead we have to do this
-        static PATTERNS: LazyLock<[&str; NUM_REPLACE_ITEMS]> =
-            LazyLock::new(|| REPLACE.map(|(p, _)| p));
-        static ESCAPED: LazyLock<[&str; NUM_REPLACE_ITEMS]> =
-            LazyLock::new(|| REPLACE.map(|(_, e)| e));
-        static MATCHER: LazyLock<AhoCorasick> =
-            LazyLock::new(|| AhoCorasick::new(PATTERNS.iter()).expect("Error setting up matcher"));
+    /// The context the template is rendered with
+    fn template_context(&self, now: DateTime<Utc>, theme: Option<&str>) -> minijinja::Value {
+        context! {
+            krate => self,
+            has_description => !self.description.is_empty(),
+            now => now.to_rfc3339(),
+            background => BACKGROUND.get().map(|b| b.vpath.as_rootless_path().to_str()),
+            theme => theme,
+        }
+    }
 
-        Self(MATCHER.replace_all(s, &*ESCAPED))
+    /// Render a PNG for this [`CrateData`] using [`typst`],
+    /// with the default [`RenderOptions`].
+    pub async fn render_as_png(self) -> Result<Vec<u8>, Error> {
+        self.render_as_png_with_options(RenderOptions::default())
+            .await
     }
-}
 
-impl From<String> for TypstString {
-    fn from(s: String) -> Self {
-        s.as_str().into()
+    /// Render a PNG for this [`CrateData`] using [`typst`], returning
+    /// a [`RenderError`] if the render exceeds the [`RenderBudget`].
+    pub async fn render_as_png_with_options(
+        self,
+        options: RenderOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let [(_, png)] = self
+            .render_all_sizes([ImageSize::Og], options)
+            .await?
+            .try_into()
+            .unwrap();
+        Ok(png)
     }
-}
 
-impl CrateData {
-    fn render_as_typst_source(&self) -> String {
-        let template = TEMPLATE_ENV.get_template(OG_TEMPLATE_NAME).unwrap();
-        template
-            .render(context! {
-                krate => self
-            })
-            .expect("Error rendering Jinja2 template")
+    /// Render a PNG for each of the passed [`ImageSize`]s. The Typst document
+    /// is compiled only once, and then rasterized once for each size.
+    pub async fn render_all_sizes(
+        self,
+        sizes: impl IntoIterator<Item = ImageSize>,
+        options: RenderOptions,
+    ) -> Result<Vec<(ImageSize, Vec<u8>)>, Error> {
+        self.render_all_sizes_holding(sizes, options, ()).await
+    }
+
+    /// Like [`Self::render_all_sizes`], but holds on to `guard`, e.g. a permit limiting
+    /// the number of concurrent renders, until rendering is done. That includes
+    /// compilation that is left to finish in the background after the deadline passed.
+    pub async fn render_all_sizes_holding(
+        self,
+        sizes: impl IntoIterator<Item = ImageSize>,
+        options: RenderOptions,
+        guard: impl Send + 'static,
+    ) -> Result<Vec<(ImageSize, Vec<u8>)>, Error> {
+        let RenderOptions {
+            budget,
+            stable,
+            theme,
+        } = options;
+        let sizes: Vec<_> = sizes.into_iter().collect();
+        let (document, guard) = self
+            .compile_within(budget.deadline, stable, theme, guard)
+            .await?;
+
+        tokio::task::spawn_blocking(move || {
+            let page = &document.pages[0];
+            let images = sizes
+                .into_iter()
+                .map(|size| Ok((size, rasterize(page, size, budget.max_pixels)?)))
+                .collect();
+            drop(guard);
+            images
+        })
+        .await
+        .unwrap()
     }
 
-    /// Render a PNG for this [`CrateData`] using [`typst`].
-    pub async fn render_as_png(self) -> Vec<u8> {
+    /// Render this [`CrateData`] in the passed [`OutputFormat`]. Raster
+    /// formats are rendered in the [`ImageSize::Og`] size.
+    pub async fn render_as(
+        self,
+        format: OutputFormat,
+        options: RenderOptions,
+    ) -> Result<Vec<u8>, Error> {
+        let RenderOptions {
+            budget,
+            stable,
+            theme,
+        } = options;
+        let (document, ()) = self
+            .compile_within(budget.deadline, stable, theme, ())
+            .await?;
+
         tokio::task::spawn_blocking(move || {
-            let typ = self.render_as_typst_source();
-            let world = OgTypstWorld::new(typ.clone());
-            let Warned { output, warnings } = typst::compile(&world);
-            if !warnings.is_empty() {
-                panic!("{warnings:?}");
-            }
-            let output = output.unwrap_or_else(|e| {
-                e.into_iter().for_each(|e| {
-                    eprintln!("Error rendering image for crate {}: {e:?}", self.name)
-                });
-                eprintln!("Source:");
-                eprintln!("================");
-                eprintln!("{typ}");
-                eprintln!("================");
-                std::process::exit(-1);
-            });
-
-            let page = &output.pages[0];
-            let pixmap = typst_render::render(page, 1.);
-            pixmap.encode_png().unwrap()
+            let page = &document.pages[0];
+            let bytes = match format {
+                OutputFormat::Svg => typst_svg::svg(page).into_bytes(),
+                OutputFormat::Pdf => typst_pdf::pdf(&document, &typst_pdf::PdfOptions::default())
+                    .map_err(|e| RenderError::Encode(format!("{e:?}")))?,
+                OutputFormat::Png | OutputFormat::Webp | OutputFormat::Jpeg => {
+                    let pixmap = rasterize_pixmap(page, ImageSize::Og, budget.max_pixels)?;
+                    encode(&pixmap, format)?
+                }
+            };
+            Ok(bytes)
         })
         .await
         .unwrap()
     }
+
+    /// Compile the Typst document, unless it takes longer than `deadline`.
+    /// `guard` is dropped once compilation is done, and returned otherwise.
+    async fn compile_within<G: Send + 'static>(
+        self,
+        deadline: Duration,
+        stable: bool,
+        theme: Option<String>,
+        guard: G,
+    ) -> Result<(Document, G), Error> {
+        // Typst compilation can't be interrupted, so if the deadline passes,
+        // the blocking task is left to finish in the background.
+        let compile =
+            tokio::task::spawn_blocking(move || (self.compile(stable, theme.as_deref()), guard));
+        let (document, guard) = tokio::time::timeout(deadline, compile)
+            .await
+            .map_err(|_| RenderError::DeadlineExceeded(deadline))?
+            .unwrap();
+        Ok((document?, guard))
+    }
+
+    /// Render a PNG like [`Self::render_as_png_with_options`], timing each stage of
+    /// the render. Blocks the current thread, and doesn't enforce the deadline of the
+    /// [`RenderBudget`], as it's meant for benchmarking.
+    pub fn render_png_timed(&self, options: &RenderOptions) -> Result<RenderTimings, Error> {
+        let start = Instant::now();
+        let typ = self.typst_source(options)?;
+        let template = start.elapsed();
+
+        let start = Instant::now();
+        let document = self.compile_source(typ, options.stable)?;
+        let compile = start.elapsed();
+
+        let start = Instant::now();
+        let pixmap =
+            rasterize_pixmap(&document.pages[0], ImageSize::Og, options.budget.max_pixels)?;
+        let rasterize = start.elapsed();
+
+        let start = Instant::now();
+        encode_png(&pixmap)?;
+        let encode = start.elapsed();
+
+        Ok(RenderTimings {
+            template,
+            compile,
+            rasterize,
+            encode,
+        })
+    }
+
+    fn compile(&self, stable: bool, theme: Option<&str>) -> Result<Document, Error> {
+        let now = if stable { *STABLE_NOW } else { Utc::now() };
+        self.compile_source(self.render_as_typst_source(now, theme)?, stable)
+    }
+
+    /// Compile the Typst source rendered from the template. Warnings, like
+    /// about missing fonts, fail the compilation, as the image would be off.
+    fn compile_source(&self, typ: String, stable: bool) -> Result<Document, Error> {
+        let world = OgTypstWorld::new(typ, stable);
+        let Warned { output, warnings } = typst::compile(&world);
+        // In stable mode, fonts the template asks for may not be
+        // available, in which case Typst falls back to a bundled font.
+        if !warnings.is_empty() && !stable {
+            return Err(RenderError::Compile(diagnostics(&warnings)).into());
+        }
+        Ok(output.map_err(|errors| RenderError::Compile(diagnostics(&errors)))?)
+    }
+}
+
+/// The messages of Typst's diagnostics, joined into one line
+fn diagnostics(diagnostics: &[SourceDiagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Render and compile a sample crate with the template in `env`, in stable mode,
+/// to make sure the template works before it replaces the current one
+pub(crate) fn trial_render(env: &minijinja::Environment<'_>) -> Result<(), Error> {
+    let data = &*SAMPLE_CRATE_DATA;
+    let typ = template::render_with(env, data.template_context(*STABLE_NOW, None))?;
+    data.compile_source(typ, true)?;
+    Ok(())
+}
+
+/// A crate with all kinds of data, to try out templates with
+static SAMPLE_CRATE_DATA: LazyLock<CrateData> = LazyLock::new(|| CrateData {
+    name: "sample-crate".parse().unwrap(),
+    description: "A \"sample\" crate, to try out templates with".into(),
+    team_owners: vec![TeamCrateOwner {
+        avatar: "https://avatars.githubusercontent.com/u/5430905?v=4".into(),
+        name: "rust-lang/libs".into(),
+        org: "rust-lang".into(),
+        url: "https://github.com/rust-lang".into(),
+    }],
+    user_owners: vec![UserCrateOwner {
+        avatar: "https://avatars.githubusercontent.com/u/17907879?v=4&s=70".into(),
+        login: "sample".into(),
+        name: Some("Sample Owner".into()),
+        url: "https://github.com/sample".into(),
+    }],
+    weekly_downloads: vec![120, 340, 560, 230, 780, 910],
+    license: Some("MIT OR Apache-2.0".into()),
+    version: Some("1.2.3".into()),
+    rust_version: Some("1.80".into()),
+    crate_size: Some(123_456),
+    published_at: Some(*STABLE_NOW),
+    num_versions: 12,
+    num_owners: 3,
+    downloads: 1_234_567,
+    recent_downloads: 12_345,
+    dependents: 42,
+    keywords: vec!["sample".into(), "template".into()],
+    categories: vec![CrateCategory {
+        name: "Development tools".into(),
+        slug: "development-tools".into(),
+    }],
+    repository: Some("https://github.com/sample/sample-crate".into()),
+    homepage: None,
+    documentation: Some("https://docs.rs/sample-crate".into()),
+    created_at: *STABLE_NOW,
+    updated_at: *STABLE_NOW,
+});
+
+/// How long each stage of rendering an image took
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderTimings {
+    /// Rendering the Jinja2 template to Typst source
+    pub template: Duration,
+    /// Compiling the Typst source
+    pub compile: Duration,
+    /// Rasterizing the compiled page
+    pub rasterize: Duration,
+    /// Encoding the rasterized page as PNG
+    pub encode: Duration,
+}
+
+/// The fixed point in time used in stable rendering mode.
+static STABLE_NOW: LazyLock<DateTime<Utc>> =
+    LazyLock::new(|| "2025-01-01T00:00:00Z".parse().unwrap());
+
+/// Options for rendering images
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Limits on the resources the render may use
+    pub budget: RenderBudget,
+    /// Render in a way that is stable across environments and over time,
+    /// so the output can be used in snapshot tests. Uses only the fonts
+    /// bundled with Typst, draws placeholders instead of fetching avatars,
+    /// and renders relative times as if it were 2025-01-01.
+    pub stable: bool,
+    /// The theme to render with, exposed to the template as the `theme`
+    /// variable. Which themes are supported is up to the template.
+    pub theme: Option<String>,
+}
+
+/// Rasterize the page as a PNG of the passed [`ImageSize`].
+fn rasterize(page: &Page, size: ImageSize, max_pixels: u64) -> Result<Vec<u8>, RenderError> {
+    encode_png(&rasterize_pixmap(page, size, max_pixels)?)
+}
+
+/// Rasterize the page in the passed [`ImageSize`].
+fn rasterize_pixmap(page: &Page, size: ImageSize, max_pixels: u64) -> Result<Pixmap, RenderError> {
+    let page_size = page.frame.size();
+    let (page_width, page_height) = (page_size.x.to_pt(), page_size.y.to_pt());
+    let (width, height) = size
+        .dimensions()
+        .unwrap_or((page_width.round() as u32, page_height.round() as u32));
+    let (scale_x, scale_y) = (width as f64 / page_width, height as f64 / page_height);
+    let scale = match size.fit() {
+        Fit::Cover => scale_x.max(scale_y),
+        Fit::Contain => scale_x.min(scale_y),
+    };
+
+    let too_large = |width: f64, height: f64| width * height > max_pixels as f64;
+    if too_large(width.into(), height.into()) || too_large(page_width * scale, page_height * scale)
+    {
+        return Err(RenderError::TooLarge {
+            width,
+            height,
+            max_pixels,
+        });
+    }
+
+    let pixmap = typst_render::render(page, scale as f32);
+    if (pixmap.width(), pixmap.height()) == (width, height) {
+        return Ok(pixmap);
+    }
+
+    // Center the rendered page on a canvas of the requested size,
+    // cropping it or filling the padding with the color of the
+    // page's top left corner.
+    let mut canvas = Pixmap::new(width, height).unwrap();
+    if let Some(corner) = pixmap.pixel(0, 0) {
+        let corner = corner.demultiply();
+        canvas.fill(Color::from_rgba8(
+            corner.red(),
+            corner.green(),
+            corner.blue(),
+            corner.alpha(),
+        ));
+    }
+    canvas.draw_pixmap(
+        (width as i32 - pixmap.width() as i32) / 2,
+        (height as i32 - pixmap.height() as i32) / 2,
+        pixmap.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+    Ok(canvas)
+}
+
+fn encode_png(pixmap: &Pixmap) -> Result<Vec<u8>, RenderError> {
+    pixmap
+        .encode_png()
+        .map_err(|e| RenderError::Encode(e.to_string()))
+}
+
+/// Encode the pixmap in a raster [`OutputFormat`]
+fn encode(pixmap: &Pixmap, format: OutputFormat) -> Result<Vec<u8>, RenderError> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let pixels = pixmap.pixels().iter().map(|pixel| pixel.demultiply());
+    let mut out = vec![];
+    let result = match format {
+        OutputFormat::Png => return encode_png(pixmap),
+        OutputFormat::Webp => {
+            let rgba: Vec<u8> = pixels
+                .flat_map(|c| [c.red(), c.green(), c.blue(), c.alpha()])
+                .collect();
+            WebPEncoder::new_lossless(&mut out).write_image(
+                &rgba,
+                width,
+                height,
+                ExtendedColorType::Rgba8,
+            )
+        }
+        // JPEG has no alpha channel, which is fine as
+        // cards are drawn on an opaque background
+        OutputFormat::Jpeg => {
+            let rgb: Vec<u8> = pixels
+                .flat_map(|c| [c.red(), c.green(), c.blue()])
+                .collect();
+            JpegEncoder::new_with_quality(&mut out, 90).write_image(
+                &rgb,
+                width,
+                height,
+                ExtendedColorType::Rgb8,
+            )
+        }
+        OutputFormat::Svg | OutputFormat::Pdf => unreachable!("{format:?} is not a raster format"),
+    };
+    result.map_err(|e| RenderError::Encode(e.to_string()))?;
+    Ok(out)
+}
+
+/// The file formats images can be rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Svg,
+    Webp,
+    Jpeg,
+    Pdf,
+}
+
+impl OutputFormat {
+    /// The format matching the extension of `path`, if any
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "svg" => Some(OutputFormat::Svg),
+            "webp" => Some(OutputFormat::Webp),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "pdf" => Some(OutputFormat::Pdf),
+            _ => None,
+        }
+    }
+
+    /// The file extension for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Svg => "svg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Pdf => "pdf",
+        }
+    }
+
+    /// The MIME type of this format
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Svg => "image/svg+xml",
+            OutputFormat::Webp => "image/webp",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// The sizes images can be rendered in.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    /// The Open Graph image, in the size defined by the template
+    Og,
+    /// 1200x600, for Twitter's `summary_large_image` cards
+    Twitter,
+    /// 1200x1200, for platforms that show square previews
+    Square,
+}
+
+/// How a page is fitted into an [`ImageSize`] with
+/// a different aspect ratio
+enum Fit {
+    /// Scale the page to fill the image, cropping the edges
+    Cover,
+    /// Scale the page to fit inside the image, padding the edges
+    Contain,
+}
+
+impl ImageSize {
+    /// The dimensions of the image in pixels, or `None`
+    /// if the dimensions of the page are to be used.
+    pub fn dimensions(self) -> Option<(u32, u32)> {
+        match self {
+            ImageSize::Og => None,
+            ImageSize::Twitter => Some((1200, 600)),
+            ImageSize::Square => Some((1200, 1200)),
+        }
+    }
+
+    fn fit(self) -> Fit {
+        match self {
+            // Twitter cards are close enough to the Open Graph
+            // aspect ratio to just crop a few pixels.
+            ImageSize::Og | ImageSize::Twitter => Fit::Cover,
+            ImageSize::Square => Fit::Contain,
+        }
+    }
+}
+
+impl fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageSize::Og => "og".fmt(f),
+            ImageSize::Twitter => "twitter".fmt(f),
+            ImageSize::Square => "square".fmt(f),
+        }
+    }
+}
+
+/// Limits on the resources a single render may use, so that
+/// pathological input can't make Typst consume unbounded CPU or memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderBudget {
+    /// Maximum wall-clock time to spend compiling the Typst document
+    pub deadline: Duration,
+    /// Maximum number of pixels of the rendered image
+    pub max_pixels: u64,
+}
+
+impl Default for RenderBudget {
+    fn default() -> Self {
+        Self {
+            deadline: Duration::from_secs(10),
+            // Leaves plenty of room for templates larger than the
+            // default 1200x630 image.
+            max_pixels: 4 * 1200 * 630,
+        }
+    }
+}
+
+/// Error raised when rendering an image fails.
+#[derive(Debug, thiserror::Error)]
+pub enum RenderError {
+    #[error("Compiling the image took longer than {0:?}")]
+    DeadlineExceeded(Duration),
+    #[error("Image of {width}x{height} pixels exceeds the limit of {max_pixels} pixels")]
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_pixels: u64,
+    },
+    #[error("Error encoding image: {0}")]
+    Encode(String),
+    #[error("Error compiling the image: {0}")]
+    Compile(String),
+}
+
+/// Directory in which templates can look up local assets,
+/// like logos and backgrounds.
+static ASSET_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set the directory in which templates can look up local assets.
+/// Paths in templates are resolved relative to this directory,
+/// and can't escape it. Can only be set once.
+pub fn set_asset_dir(path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref().canonicalize()?;
+    ASSET_DIR
+        .set(path)
+        .map_err(|_| anyhow::anyhow!("Asset directory was already set"))?;
+    Ok(())
+}
+
+/// Spaces out avatar fetches, so GitHub doesn't rate limit us
+struct RateLimiter {
+    interval: Duration,
+    next: std::sync::Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    /// Wait until the next slot is free
+    async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(tokio::time::Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Limits avatar fetches, if set
+static AVATAR_RATE_LIMIT: OnceLock<RateLimiter> = OnceLock::new();
+
+/// Fetch at most `per_second` avatars per second, across all renders.
+/// Cached avatars don't count. Can only be set once.
+pub fn set_avatar_rate_limit(per_second: u64) -> Result<(), Error> {
+    let limiter = RateLimiter {
+        interval: Duration::from_secs(1) / per_second.clamp(1, u32::MAX.into()) as u32,
+        next: std::sync::Mutex::new(tokio::time::Instant::now()),
+    };
+    AVATAR_RATE_LIMIT
+        .set(limiter)
+        .map_err(|_| anyhow::anyhow!("Avatar rate limit was already set"))?;
+    Ok(())
+}
+
+/// Plain gray square, used in place of avatars in stable rendering mode.
+static PLACEHOLDER_AVATAR: LazyLock<Bytes> = LazyLock::new(|| {
+    let mut pixmap = Pixmap::new(70, 70).unwrap();
+    pixmap.fill(Color::from_rgba8(0xAA, 0xAA, 0xAA, 0xFF));
+    Bytes::from(pixmap.encode_png().unwrap())
+});
+
+/// A background image, made available to the template
+/// under its virtual path.
+struct Background {
+    vpath: VirtualPath,
+    data: Bytes,
+}
+
+static BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+/// Load a background image for the template to use, from either a local
+/// path or an HTTP(S) URL. PNG, JPEG, GIF and SVG images are supported.
+/// Can only be set once.
+pub async fn load_background(source: &str) -> Result<(), Error> {
+    let data = if source.starts_with("http://") || source.starts_with("https://") {
+        HTTP_CLIENT
+            .get(source)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec()
+    } else {
+        tokio::fs::read(source).await?
+    };
+    // Typst infers the image format from the extension
+    let extension = Path::new(source.split(['?', '#']).next().unwrap_or_default())
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .filter(|e| matches!(e.as_str(), "png" | "jpg" | "jpeg" | "gif" | "svg"))
+        .unwrap_or_else(|| "png".to_string());
+    let background = Background {
+        vpath: VirtualPath::new(format!("/og-loc-background.{extension}")),
+        data: Bytes::from(data),
+    };
+    BACKGROUND
+        .set(background)
+        .map_err(|_| anyhow::anyhow!("Background was already set"))?;
+    Ok(())
 }
 
 /// Simple [`typst::World`] implementation that
@@ -137,6 +765,8 @@ impl CrateData {
 struct OgTypstWorld {
     shared: Arc<OgTypstWorldShared>,
     source: Source,
+    /// See [`RenderOptions::stable`]
+    stable: bool,
 }
 
 struct OgTypstWorldShared {
@@ -147,9 +777,8 @@ struct OgTypstWorldShared {
 }
 
 impl OgTypstWorld {
-    fn new(source: String) -> Self {
-        static SHARED: LazyLock<Arc<OgTypstWorldShared>> = LazyLock::new(|| {
-            let fonts = Fonts::searcher().search();
+    fn new(source: String, stable: bool) -> Self {
+        fn init_shared(fonts: Fonts) -> Arc<OgTypstWorldShared> {
             let shared = OgTypstWorldShared {
                 library: LazyHash::new(Library::default()),
                 book: LazyHash::new(fonts.book),
@@ -160,12 +789,48 @@ impl OgTypstWorld {
                 fonts: fonts.fonts,
             };
             Arc::new(shared)
-        });
+        }
+
+        static SHARED: LazyLock<Arc<OgTypstWorldShared>> =
+            LazyLock::new(|| init_shared(Fonts::searcher().include_embedded_fonts(false).search()));
+        static STABLE_SHARED: LazyLock<Arc<OgTypstWorldShared>> =
+            LazyLock::new(|| init_shared(Fonts::searcher().include_system_fonts(false).search()));
 
         Self {
             source: Source::detached(source),
-            shared: SHARED.clone(),
+            shared: if stable {
+                STABLE_SHARED.clone()
+            } else {
+                SHARED.clone()
+            },
+            stable,
+        }
+    }
+}
+
+impl OgTypstWorld {
+    /// Load a local asset, looking in the asset directory first,
+    /// and falling back to the bundled assets.
+    fn asset(&self, vpath: &VirtualPath) -> FileResult<Bytes> {
+        if let Some(asset_dir) = ASSET_DIR.get() {
+            let path = vpath.resolve(asset_dir).ok_or(FileError::AccessDenied)?;
+            match path.canonicalize() {
+                // Guard against symlinks pointing outside of the asset directory
+                Ok(path) if !path.starts_with(asset_dir) => return Err(FileError::AccessDenied),
+                Ok(path) => {
+                    return std::fs::read(&path)
+                        .map(Bytes::from)
+                        .map_err(|e| FileError::from_io(e, &path))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(FileError::from_io(e, &path)),
+            }
+        }
+
+        if vpath == &VirtualPath::new("/cargo.png") {
+            return Ok(Bytes::from_static(include_bytes!("../cargo.png")));
         }
+        Err(FileError::NotFound(vpath.as_rootless_path().into()))
     }
 }
 
@@ -187,8 +852,16 @@ impl typst::World for OgTypstWorld {
     }
 
     fn file(&self, id: FileId) -> FileResult<Bytes> {
-        if id.vpath() == &VirtualPath::new("/cargo.png") {
-            return Ok(Bytes::from_static(include_bytes!("../cargo.png")));
+        let rootless_path = id.vpath().as_rootless_path();
+        let is_url = rootless_path.starts_with("http:") || rootless_path.starts_with("https:");
+        if let Some(background) = BACKGROUND.get().filter(|b| &b.vpath == id.vpath()) {
+            return Ok(background.data.clone());
+        }
+        if !is_url {
+            return self.asset(id.vpath());
+        }
+        if self.stable {
+            return Ok(PLACEHOLDER_AVATAR.clone());
         }
 
         self.shared
@@ -196,7 +869,10 @@ impl typst::World for OgTypstWorld {
             .get_with(id, || {
                 tokio::runtime::Handle::current().block_on(async {
                     // TODO parse and validate URL
-                    let url = id.vpath().as_rootless_path().to_str()?;
+                    let url = rootless_path.to_str()?;
+                    if let Some(limiter) = AVATAR_RATE_LIMIT.get() {
+                        limiter.wait().await;
+                    }
                     let body = HTTP_CLIENT
                         .get(url)
                         .send()
@@ -225,11 +901,21 @@ impl typst::World for OgTypstWorld {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::LazyLock;
+    use std::{sync::LazyLock, time::Duration};
+
+    use crate::{
+        augment::{CrateDb, LoadFilter},
+        convert::UserCrateOwner,
+        error::Error,
+    };
 
-    use crate::{augment::CrateDb, convert::UserCrateOwner};
+    use chrono::Utc;
+    use test_case::case;
 
-    use super::{CrateData, TeamCrateOwner};
+    use super::{
+        CrateCategory, CrateData, OutputFormat, RenderBudget, RenderError, RenderOptions,
+        TeamCrateOwner,
+    };
 
     static KNIEN_CRATE_DATA: LazyLock<CrateData> = LazyLock::new(|| CrateData {
         name: "knien".parse().unwrap(),
@@ -237,12 +923,50 @@ mod tests {
         user_owners: vec![
             UserCrateOwner {
                 avatar: "https://avatars.githubusercontent.com/u/17907879?v=4&s=70".into(),
+                login: "hdoordt".into(),
+                name: Some("Henk Oordt".into()),
+                url: "https://github.com/hdoordt".into(),
             },
             UserCrateOwner {
                 avatar: "https://avatars.githubusercontent.com/u/8545127?v=4&s=70".into(),
+                login: "marcoow".into(),
+                name: None,
+                url: "https://github.com/marcoow".into(),
             },
         ],
         team_owners: vec![],
+        weekly_downloads: vec![],
+        license: Some("MIT OR Apache-2.0".into()),
+        version: Some("0.0.10".into()),
+        rust_version: None,
+        crate_size: Some(13_674),
+        published_at: None,
+        num_versions: 10,
+        num_owners: 2,
+        downloads: 0,
+        recent_downloads: 0,
+        dependents: 0,
+        keywords: vec![
+            "amqp".into(),
+            "broker".into(),
+            "queue".into(),
+            "rabbitmq".into(),
+        ],
+        categories: vec![
+            CrateCategory {
+                name: "Asynchronous".into(),
+                slug: "asynchronous".into(),
+            },
+            CrateCategory {
+                name: "Web programming".into(),
+                slug: "web-programming".into(),
+            },
+        ],
+        repository: Some("https://github.com/hdoordt/knien".into()),
+        homepage: Some("https://github.com/hdoordt/knien".into()),
+        documentation: Some("https://docs.rs/knien".into()),
+        created_at: "2023-02-14T12:51:08Z".parse().unwrap(),
+        updated_at: "2023-10-31T10:16:24Z".parse().unwrap(),
     });
 
     static DOCKER_RUST_API_CRATE_DATA: LazyLock<CrateData> = LazyLock::new(|| {
@@ -258,8 +982,29 @@ mod tests {
             UserCrateOwner {
                 avatar:
                     "https://avatars.githubusercontent.com/u/40946917?v=4&s=70".into(),
+                login: "user-40946917".into(),
+                name: None,
+                url: "https://github.com/user-40946917".into(),
             },
         ],
+        weekly_downloads: vec![],
+        license: Some("Apache-2.0".into()),
+        version: Some("1.45.0".into()),
+        rust_version: None,
+        crate_size: Some(121_374),
+        published_at: None,
+        num_versions: 6,
+        num_owners: 1,
+        downloads: 38_120,
+        recent_downloads: 2_210,
+        dependents: 1,
+        keywords: vec![],
+        categories: vec![],
+        repository: None,
+        homepage: None,
+        documentation: None,
+        created_at: "2024-06-03T08:12:45Z".parse().unwrap(),
+        updated_at: "2024-06-03T08:12:45Z".parse().unwrap(),
     }
     });
 
@@ -272,25 +1017,67 @@ mod tests {
         team_owners: vec![
             TeamCrateOwner {
                 avatar: "https://avatars1.githubusercontent.com/u/14631425?v=4&s=70".into(),
+                name: "rust-lang-nursery/log-owners".into(),
+                org: "rust-lang-nursery".into(),
+                url: "https://github.com/orgs/rust-lang-nursery/teams/log-owners".into(),
             },
             TeamCrateOwner {
                 avatar: "https://avatars.githubusercontent.com/u/14631425?v=3&s=70".into(),
+                name: "rust-lang-nursery/libs".into(),
+                org: "rust-lang-nursery".into(),
+                url: "https://github.com/orgs/rust-lang-nursery/teams/libs".into(),
             },
             TeamCrateOwner {
                 avatar: "https://avatars0.githubusercontent.com/u/68172612?v=4&s=70".into(),
+                name: "rust-cli/maintainers".into(),
+                org: "rust-cli".into(),
+                url: "https://github.com/orgs/rust-cli/teams/maintainers".into(),
             },
         ],
         user_owners: vec![
             UserCrateOwner {
                 avatar: "https://avatars1.githubusercontent.com/u/1203825?v=4&s=70".into(),
+                login: "user-1203825".into(),
+                name: None,
+                url: "https://github.com/user-1203825".into(),
             },
             UserCrateOwner {
                 avatar: "https://avatars.githubusercontent.com/u/1455697?v=4&s=70".into(),
+                login: "user-1455697".into(),
+                name: None,
+                url: "https://github.com/user-1455697".into(),
             },
             UserCrateOwner {
                 avatar: "https://avatars1.githubusercontent.com/u/11137?v=4&s=70".into(),
+                login: "user-11137".into(),
+                name: None,
+                url: "https://github.com/user-11137".into(),
             },
         ],
+        weekly_downloads: vec![
+            1_412_311, 1_502_114, 1_488_270, 1_391_923, 1_120_004, 1_614_850, 1_702_113, 1_688_421,
+            1_731_995, 1_650_377, 1_799_802, 1_845_120,
+        ],
+        license: Some("MIT OR Apache-2.0".into()),
+        version: Some("0.11.6".into()),
+        rust_version: Some("1.71".into()),
+        crate_size: Some(32_476),
+        published_at: None,
+        num_versions: 52,
+        num_owners: 8,
+        downloads: 407_882_150,
+        recent_downloads: 20_412_336,
+        dependents: 4_512,
+        keywords: vec!["logging".into()],
+        categories: vec![CrateCategory {
+            name: "Development tools::Debugging".into(),
+            slug: "development-tools::debugging".into(),
+        }],
+        repository: Some("https://github.com/rust-cli/env_logger".into()),
+        homepage: None,
+        documentation: None,
+        created_at: "2015-01-22T20:34:51Z".parse().unwrap(),
+        updated_at: "2025-01-07T15:02:38Z".parse().unwrap(),
     });
 
     static GOOGLEAPIS_TONIC_GOOGLE_CLOUD_SAASACCELERATOR_MANAGEMENT_LOGS_V1_CRATE_DATA: LazyLock<
@@ -303,48 +1090,210 @@ mod tests {
         team_owners: vec![],
         user_owners: vec![UserCrateOwner {
             avatar: "https://avatars.githubusercontent.com/u/1221346?v=4&s=70".into(),
+            login: "user-1221346".into(),
+            name: None,
+            url: "https://github.com/user-1221346".into(),
         }],
+        weekly_downloads: vec![],
+        license: Some("Apache-2.0".into()),
+        version: Some("0.4.0".into()),
+        rust_version: None,
+        crate_size: Some(6_712),
+        published_at: None,
+        num_versions: 5,
+        num_owners: 1,
+        downloads: 1_254,
+        recent_downloads: 87,
+        dependents: 0,
+        keywords: vec!["google".into(), "googleapis".into(), "tonic".into()],
+        categories: vec![],
+        repository: None,
+        homepage: None,
+        documentation: None,
+        created_at: "2024-09-18T03:27:16Z".parse().unwrap(),
+        updated_at: "2025-01-13T03:40:52Z".parse().unwrap(),
     });
 
     #[test]
     fn render_typst_source() {
-        let rendered = KNIEN_CRATE_DATA.render_as_typst_source();
+        let rendered = KNIEN_CRATE_DATA
+            .render_as_typst_source(Utc::now(), None)
+            .unwrap();
+        insta::assert_snapshot!(rendered);
+    }
+
+    #[test]
+    fn render_typst_source_no_description() {
+        let data = CrateData {
+            description: String::new(),
+            ..KNIEN_CRATE_DATA.clone()
+        };
+        let rendered = data.render_as_typst_source(Utc::now(), None).unwrap();
+        insta::assert_snapshot!(rendered);
+    }
+
+    #[test]
+    fn render_typst_source_team_owners() {
+        let rendered = ENV_LOGGER_CRATE_DATA
+            .render_as_typst_source(Utc::now(), None)
+            .unwrap();
         insta::assert_snapshot!(rendered);
     }
 
+    #[test]
+    fn alt_text() {
+        assert_eq!(
+            KNIEN_CRATE_DATA.alt_text(),
+            "knien — Typed RabbitMQ interfacing for async Rust. 2 owners, MIT OR Apache-2.0"
+        );
+        assert_eq!(
+            DOCKER_RUST_API_CRATE_DATA.alt_text(),
+            "docker-rust-api — The Engine API is an HTTP API served by Docker Engine. It is the API the Docker client uses to communicate. 1 owner, Apache-2.0"
+        );
+        let data = CrateData {
+            description: String::new(),
+            license: None,
+            ..KNIEN_CRATE_DATA.clone()
+        };
+        assert_eq!(data.alt_text(), "knien. 2 owners");
+    }
+
+    /// Render `data` in stable mode, so the snapshots don't depend on the fonts installed.
+    async fn render_stable_png(data: &CrateData) -> Vec<u8> {
+        let options = RenderOptions {
+            stable: true,
+            ..Default::default()
+        };
+        data.clone()
+            .render_as_png_with_options(options)
+            .await
+            .unwrap()
+    }
+
     #[tokio::test]
     async fn render_png() {
-        let rendered = KNIEN_CRATE_DATA.clone().render_as_png().await;
+        let rendered = render_stable_png(&KNIEN_CRATE_DATA).await;
         insta::assert_binary_snapshot!(".png", rendered);
     }
 
     #[tokio::test]
     async fn render_png_many_owners() {
-        let rendered = ENV_LOGGER_CRATE_DATA.clone().render_as_png().await;
+        let rendered = render_stable_png(&ENV_LOGGER_CRATE_DATA).await;
         insta::assert_binary_snapshot!(".png", rendered);
     }
 
     #[tokio::test]
     async fn render_png_long_description() {
-        let rendered = DOCKER_RUST_API_CRATE_DATA.clone().render_as_png().await;
+        let rendered = render_stable_png(&DOCKER_RUST_API_CRATE_DATA).await;
         insta::assert_binary_snapshot!(".png", rendered);
     }
 
     #[tokio::test]
     async fn render_png_long_crate_name() {
-        let rendered = GOOGLEAPIS_TONIC_GOOGLE_CLOUD_SAASACCELERATOR_MANAGEMENT_LOGS_V1_CRATE_DATA
+        let rendered = render_stable_png(
+            &GOOGLEAPIS_TONIC_GOOGLE_CLOUD_SAASACCELERATOR_MANAGEMENT_LOGS_V1_CRATE_DATA,
+        )
+        .await;
+        insta::assert_binary_snapshot!(".png", rendered);
+    }
+
+    #[case(OutputFormat::Png, b"\x89PNG")]
+    #[case(OutputFormat::Svg, b"<svg")]
+    #[case(OutputFormat::Webp, b"RIFF")]
+    #[case(OutputFormat::Jpeg, b"\xff\xd8\xff")]
+    #[case(OutputFormat::Pdf, b"%PDF")]
+    #[tokio::test]
+    async fn render_as_format(format: OutputFormat, magic: &[u8]) {
+        let options = RenderOptions {
+            stable: true,
+            ..Default::default()
+        };
+        let rendered = KNIEN_CRATE_DATA
             .clone()
-            .render_as_png()
+            .render_as(format, options)
+            .await
+            .unwrap();
+        assert!(rendered.starts_with(magic));
+    }
+
+    #[test]
+    fn render_png_timed() {
+        let options = RenderOptions {
+            stable: true,
+            ..Default::default()
+        };
+        let timings = KNIEN_CRATE_DATA.render_png_timed(&options).unwrap();
+        assert!(!timings.compile.is_zero());
+    }
+
+    #[tokio::test]
+    async fn render_too_large() {
+        let options = RenderOptions {
+            budget: RenderBudget {
+                max_pixels: 1200,
+                ..Default::default()
+            },
+            stable: true,
+            ..Default::default()
+        };
+        let result = KNIEN_CRATE_DATA
+            .clone()
+            .render_as_png_with_options(options)
             .await;
-        insta::assert_binary_snapshot!(".png", rendered);
+        assert!(matches!(
+            result,
+            Err(Error::Render(RenderError::TooLarge {
+                max_pixels: 1200,
+                ..
+            }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn render_deadline_exceeded() {
+        let options = RenderOptions {
+            budget: RenderBudget {
+                deadline: Duration::ZERO,
+                ..Default::default()
+            },
+            stable: true,
+            ..Default::default()
+        };
+        // Typst caches compilations, so use data no other test renders
+        let data = CrateData {
+            description: "Only rendered by the deadline test".into(),
+            ..KNIEN_CRATE_DATA.clone()
+        };
+        let result = data.render_as_png_with_options(options).await;
+        assert!(matches!(
+            result,
+            Err(Error::Render(RenderError::DeadlineExceeded(Duration::ZERO)))
+        ));
     }
 
     #[tokio::test]
     async fn augment_crate_data() {
-        let db = CrateDb::preload_one("./db-dump.tar.gz", "knien".into())
+        let db = CrateDb::builder("./db-dump.tar.gz")
+            .filter(LoadFilter::Single("knien".into()))
+            .load()
             .await
             .unwrap();
         let data = db.augment_crate_spec("knien".parse().unwrap()).unwrap();
+        // Download and dependent counts change with every dump, so don't compare them
+        assert_eq!(data.weekly_downloads.len(), 12);
+        // The timestamps are only checked for consistency
+        assert!(data.created_at <= data.updated_at);
+        assert!(data.published_at.is_some_and(|at| at <= data.updated_at));
+        let data = CrateData {
+            weekly_downloads: vec![],
+            downloads: 0,
+            recent_downloads: 0,
+            dependents: 0,
+            created_at: KNIEN_CRATE_DATA.created_at,
+            updated_at: KNIEN_CRATE_DATA.updated_at,
+            published_at: KNIEN_CRATE_DATA.published_at,
+            ..data
+        };
         assert_eq!(&data, &*KNIEN_CRATE_DATA);
     }
 }
@@ -0,0 +1,469 @@
+use std::{
+    collections::BTreeMap,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    augment::{
+        download_weeks, normalize_crate_name, team_login_name, team_org, team_profile_url,
+        truncate_description, user_profile_url, week_start, weekly_totals, MAX_KEYWORDS,
+    },
+    convert::{CrateCategory, CrateData, TeamCrateOwner, UserCrateOwner},
+    error::Error,
+    spec::CrateName,
+    HTTP_CLIENT,
+};
+
+/// Base URL of the crates.io API
+const API_URL: &str = "https://crates.io/api/v1";
+
+/// The crates.io crawler policy asks for no more than one request per second
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a request that failed with a server or network error,
+/// or because of rate limiting, is retried
+const MAX_RETRIES: u32 = 3;
+
+/// How long to wait before retrying a failed request. Doubles with each retry
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The longest delay asked for by the API to wait for before retrying.
+/// Requests that would need to wait longer fail right away.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// How long fetched crate data is reused
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The earliest time of the next request to the crates.io API
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::const_new(None);
+
+/// Crate data fetched from the API, by [normalized](normalize_crate_name) crate name. Crates
+/// that don't exist are cached as `None`.
+static CACHE: LazyLock<moka::sync::Cache<String, Option<CrateData>>> = LazyLock::new(|| {
+    moka::sync::Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(CACHE_TTL)
+        .build()
+});
+
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: ApiCrate,
+    versions: Vec<ApiVersion>,
+    #[serde(default)]
+    categories: Vec<ApiCategory>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrate {
+    /// The name as published, which may be spelled differently than requested
+    name: CrateName,
+    description: Option<String>,
+    downloads: u64,
+    recent_downloads: Option<u64>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    repository: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    default_version: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiVersion {
+    num: String,
+    license: Option<String>,
+    rust_version: Option<String>,
+    crate_size: Option<u64>,
+    created_at: DateTime<Utc>,
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCategory {
+    category: String,
+    slug: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OwnersResponse {
+    users: Vec<ApiOwner>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiOwner {
+    kind: String,
+    login: String,
+    name: Option<String>,
+    avatar: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseDependenciesResponse {
+    meta: ReverseDependenciesMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseDependenciesMeta {
+    total: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadsResponse {
+    version_downloads: Vec<ApiDownloads>,
+    meta: DownloadsMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadsMeta {
+    /// Downloads of versions not listed in `version_downloads`
+    extra_downloads: Vec<ApiDownloads>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiDownloads {
+    date: NaiveDate,
+    downloads: u64,
+}
+
+/// Fetch the data of a crate from the crates.io API. Results are cached
+/// for an hour, and requests are spaced out to respect the crawler policy.
+pub async fn fetch_crate_data(name: CrateName) -> Result<CrateData, Error> {
+    fetch_cached(name, None).await
+}
+
+/// Like [`fetch_crate_data`], but with the version-specific data, like the
+/// license, of `version` instead of the default version.
+/// Returns [`Error::NotFound`] if the crate has no such version.
+pub async fn fetch_crate_version_data(name: CrateName, version: &str) -> Result<CrateData, Error> {
+    fetch_cached(name, Some(version)).await
+}
+
+async fn fetch_cached(name: CrateName, version: Option<&str>) -> Result<CrateData, Error> {
+    // `Serde-JSON` and `serde_json` are the same crate
+    let key = match version {
+        Some(version) => format!("{}@{version}", normalize_crate_name(name.as_ref())),
+        None => normalize_crate_name(name.as_ref()),
+    };
+    if let Some(cached) = CACHE.get(&key) {
+        return cached.ok_or(Error::NotFound);
+    }
+    match fetch_uncached(name, version).await {
+        Ok(data) => {
+            CACHE.insert(key, Some(data.clone()));
+            Ok(data)
+        }
+        Err(Error::NotFound) => {
+            CACHE.insert(key, None);
+            Err(Error::NotFound)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Fetch the data of a crate from the crates.io API, bypassing the cache.
+/// The cache is updated with the fetched data.
+pub async fn refresh_crate_data(name: CrateName) -> Result<CrateData, Error> {
+    let data = fetch_uncached(name.clone(), None).await?;
+    CACHE.insert(normalize_crate_name(name.as_ref()), Some(data.clone()));
+    Ok(data)
+}
+
+async fn fetch_uncached(name: CrateName, version: Option<&str>) -> Result<CrateData, Error> {
+    // Not printed to stdout, which may be used for output, e.g. by `one-shot --out -`
+    eprintln!("🌐 Fetching crate '{name}' from the crates.io API");
+    let CrateResponse {
+        krate,
+        versions,
+        categories,
+    } = get(&format!("/crates/{name}")).await?;
+    let OwnersResponse { users: owners } = get(&format!("/crates/{name}/owners")).await?;
+    let downloads: DownloadsResponse = get(&format!("/crates/{name}/downloads")).await?;
+    let reverse_dependencies: ReverseDependenciesResponse =
+        get(&format!("/crates/{name}/reverse_dependencies?per_page=1")).await?;
+
+    let num_versions = versions.len().try_into().unwrap_or(u32::MAX);
+    let selected = match version {
+        Some(version) => Some(
+            versions
+                .into_iter()
+                .find(|v| v.num == version)
+                .ok_or(Error::NotFound)?,
+        ),
+        None => current_version(versions, krate.default_version.as_deref()),
+    };
+    let (version, license, rust_version, crate_size, published_at) = selected
+        .map(|v| {
+            (
+                Some(v.num),
+                v.license,
+                v.rust_version,
+                v.crate_size,
+                Some(v.created_at),
+            )
+        })
+        .unwrap_or_default();
+
+    let num_owners = owners.len();
+    let mut user_owners = vec![];
+    let mut team_owners = vec![];
+    for owner in owners {
+        let Some(avatar) = owner.avatar else {
+            continue;
+        };
+        let avatar = format!("{avatar}&s=70");
+        match owner.kind.as_str() {
+            "team" => {
+                let name = team_login_name(&owner.login);
+                team_owners.push(TeamCrateOwner {
+                    avatar,
+                    org: team_org(&name).to_string(),
+                    url: team_profile_url(&name),
+                    name,
+                })
+            }
+            _ => user_owners.push(UserCrateOwner {
+                avatar,
+                url: user_profile_url(&owner.login),
+                login: owner.login,
+                name: owner.name,
+            }),
+        }
+    }
+    user_owners.truncate(3);
+    team_owners.truncate(3);
+
+    let mut keywords = krate.keywords;
+    keywords.sort_unstable();
+    keywords.truncate(MAX_KEYWORDS);
+
+    let mut categories: Vec<_> = categories
+        .into_iter()
+        .map(|c| CrateCategory {
+            name: c.category,
+            slug: c.slug,
+        })
+        .collect();
+    categories.sort_unstable_by(|a, b| a.slug.cmp(&b.slug));
+
+    Ok(CrateData {
+        name: krate.name,
+        description: truncate_description(krate.description.unwrap_or_default()),
+        team_owners,
+        user_owners,
+        weekly_downloads: weekly_downloads(downloads),
+        license,
+        version,
+        rust_version,
+        crate_size,
+        published_at,
+        num_versions,
+        num_owners,
+        downloads: krate.downloads,
+        recent_downloads: krate.recent_downloads.unwrap_or_default(),
+        dependents: reverse_dependencies.meta.total,
+        keywords,
+        categories,
+        repository: krate.repository,
+        homepage: krate.homepage,
+        documentation: krate.documentation,
+        created_at: krate.created_at,
+        updated_at: krate.updated_at,
+    })
+}
+
+/// The default version, or if that was yanked, the most
+/// recently published version that wasn't yanked, if any
+fn current_version(mut versions: Vec<ApiVersion>, default: Option<&str>) -> Option<ApiVersion> {
+    let default = versions
+        .iter()
+        .position(|v| Some(v.num.as_str()) == default)?;
+    let current = if versions[default].yanked {
+        versions
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| !v.yanked)
+            .max_by_key(|(_, v)| v.created_at)
+            .map_or(default, |(idx, _)| idx)
+    } else {
+        default
+    };
+    Some(versions.swap_remove(current))
+}
+
+/// Aggregate the daily downloads into the totals of full weeks
+fn weekly_downloads(downloads: DownloadsResponse) -> Vec<u64> {
+    let mut weeks = BTreeMap::new();
+    let mut last_date = NaiveDate::MIN;
+    for ApiDownloads { date, downloads } in downloads
+        .version_downloads
+        .into_iter()
+        .chain(downloads.meta.extra_downloads)
+    {
+        last_date = last_date.max(date);
+        *weeks.entry(week_start(date)).or_default() += downloads;
+    }
+    weekly_totals(Some(&weeks), &download_weeks(last_date))
+}
+
+/// Get `path` from the crates.io API. Requests that fail with a server or network
+/// error, or because of rate limiting, are retried up to [`MAX_RETRIES`] times,
+/// after the delay the API asks for in its `Retry-After` header, if any, or else
+/// with exponential backoff.
+async fn get<T: DeserializeOwned>(path: &str) -> Result<T, Error> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let Failed { error, retry_after } = match get_once(path).await {
+            Ok(response) => return Ok(response),
+            Err(failed) => failed,
+        };
+        let retryable = retry_after.is_some() || is_transient(&error);
+        if attempt == MAX_RETRIES || !retryable || retry_after > Some(MAX_RETRY_AFTER) {
+            return Err(error);
+        }
+        attempt += 1;
+        let delay = retry_after.unwrap_or(backoff);
+        eprintln!("Retrying {path} in {delay:?} ({attempt}/{MAX_RETRIES}): {error}");
+        // Hold back all requests, as the API would likely reject them as well
+        delay_requests(delay).await;
+        backoff *= 2;
+    }
+}
+
+/// A failed request to the crates.io API
+struct Failed {
+    error: Error,
+    /// How long the API asked to wait before trying again
+    retry_after: Option<Duration>,
+}
+
+impl<E: Into<Error>> From<E> for Failed {
+    fn from(error: E) -> Self {
+        Self {
+            error: error.into(),
+            retry_after: None,
+        }
+    }
+}
+
+/// Do a GET request to the crates.io API, waiting for
+/// [`MIN_REQUEST_INTERVAL`] since the last request,
+/// or for longer if requests are being held back.
+async fn get_once<T: DeserializeOwned>(path: &str) -> Result<T, Failed> {
+    {
+        let mut next_request = NEXT_REQUEST.lock().await;
+        if let Some(next_request) = *next_request {
+            tokio::time::sleep_until(next_request.into()).await;
+        }
+        *next_request = Some(Instant::now() + MIN_REQUEST_INTERVAL);
+    }
+
+    let response = HTTP_CLIENT.get(format!("{API_URL}{path}")).send().await?;
+    match response.status() {
+        StatusCode::NOT_FOUND => Err(Error::NotFound.into()),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+            let retry_after = retry_after(response.headers());
+            let error = response.error_for_status().unwrap_err();
+            Err(Failed {
+                error: error.into(),
+                retry_after,
+            })
+        }
+        _ => Ok(response.error_for_status()?.json().await?),
+    }
+}
+
+/// Hold back all requests to the crates.io API for `delay`
+async fn delay_requests(delay: Duration) {
+    let mut next_request = NEXT_REQUEST.lock().await;
+    let until = Instant::now() + delay;
+    *next_request = Some(next_request.map_or(until, |next_request| next_request.max(until)));
+}
+
+/// The delay requested by a `Retry-After` header, given
+/// either in seconds or as an HTTP date, if any
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Whether a failed request may succeed when retried
+fn is_transient(e: &Error) -> bool {
+    let Error::Http(e) = e else {
+        return false;
+    };
+    e.is_connect()
+        || e.is_timeout()
+        || e.status().is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    use super::{retry_after, ApiDownloads, DownloadsMeta, DownloadsResponse};
+
+    #[test]
+    fn test_retry_after() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    #[test]
+    fn test_weekly_downloads() {
+        let day = |date: &str, downloads| ApiDownloads {
+            date: date.parse().unwrap(),
+            downloads,
+        };
+        let downloads = DownloadsResponse {
+            version_downloads: vec![
+                day("2025-01-05", 10),
+                day("2025-01-06", 20),
+                day("2025-01-12", 30),
+                day("2025-01-14", 40),
+            ],
+            meta: DownloadsMeta {
+                extra_downloads: vec![day("2025-01-12", 5)],
+            },
+        };
+        let weekly = super::weekly_downloads(downloads);
+        assert_eq!(weekly.len(), 12);
+        // The week of 2025-01-13 is not over yet
+        assert_eq!(weekly[10..], [10, 55]);
+    }
+}
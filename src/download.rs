@@ -0,0 +1,374 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{
+    header::{HeaderMap, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    StatusCode, Url,
+};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::{error::Error, HTTP_CLIENT};
+
+/// Location of the latest crates.io database dump
+pub const DB_DUMP_URL: &str = "https://static.crates.io/db-dump.tar.gz";
+
+/// Where to find the database dump: either a local path,
+/// or an HTTP(S) URL to download it from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbDumpLocation {
+    Path(PathBuf),
+    Url(Url),
+}
+
+impl DbDumpLocation {
+    /// The local path of the database dump. For URLs,
+    /// this is the location the dump is downloaded to.
+    pub fn local_path(&self) -> PathBuf {
+        match self {
+            DbDumpLocation::Path(path) => path.clone(),
+            DbDumpLocation::Url(url) => {
+                let file_name = url
+                    .path_segments()
+                    .and_then(|mut s| s.next_back())
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or("db-dump.tar.gz");
+                cache_dir().join(file_name)
+            }
+        }
+    }
+}
+
+impl FromStr for DbDumpLocation {
+    type Err = url::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Self::Url(s.parse()?));
+        }
+        Ok(Self::Path(s.into()))
+    }
+}
+
+impl fmt::Display for DbDumpLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbDumpLocation::Path(path) => path.display().fmt(f),
+            DbDumpLocation::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+/// The expected SHA-256 checksum of a downloaded database dump: either
+/// the hex-encoded digest itself, or an HTTP(S) URL to fetch it from.
+/// Checksum files in the `sha256sum` format are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256(String),
+    Url(Url),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Not a SHA-256 checksum or HTTP(S) URL: {0}")]
+pub struct InvalidChecksum(String);
+
+impl Checksum {
+    /// The hex-encoded SHA-256 digest, fetching it if needed
+    async fn resolve(&self) -> Result<String, Error> {
+        match self {
+            Checksum::Sha256(digest) => Ok(digest.clone()),
+            Checksum::Url(url) => {
+                let body = HTTP_CLIENT
+                    .get(url.clone())
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                parse_sha256(body.split_whitespace().next().unwrap_or_default())
+                    .ok_or_else(|| anyhow::anyhow!("No SHA-256 checksum found at {url}").into())
+            }
+        }
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = InvalidChecksum;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return s
+                .parse()
+                .map(Self::Url)
+                .map_err(|_| InvalidChecksum(s.to_string()));
+        }
+        parse_sha256(s)
+            .map(Self::Sha256)
+            .ok_or_else(|| InvalidChecksum(s.to_string()))
+    }
+}
+
+/// Normalize `s` to a lowercase hex-encoded SHA-256 digest, if it is one
+fn parse_sha256(s: &str) -> Option<String> {
+    (s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())).then(|| s.to_ascii_lowercase())
+}
+
+/// Compute the hex-encoded SHA-256 digest of the file at `path`
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The directory downloaded database dumps are cached in
+fn cache_dir() -> PathBuf {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join(env!("CARGO_PKG_NAME"))
+}
+
+/// Make sure a database dump is present at `path`, downloading
+/// it from `url` if it's missing or was modified more than `max_age` ago.
+/// Downloaded dumps are verified against `checksum`, if given.
+pub async fn ensure_db_dump(
+    path: &Path,
+    url: &str,
+    max_age: Duration,
+    checksum: Option<&Checksum>,
+) -> Result<(), Error> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) => {
+            let age = SystemTime::now()
+                .duration_since(metadata.modified()?)
+                .unwrap_or_default();
+            if age <= max_age {
+                return Ok(());
+            }
+            println!(
+                "📦 Database dump at {} is {} hours old, downloading a new one",
+                path.display(),
+                age.as_secs() / 3600
+            );
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!(
+                "📦 No database dump found at {}, downloading one",
+                path.display()
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    download(url, path, checksum).await
+}
+
+/// Download the file at `url` to `path`, showing progress on stderr.
+/// The file is downloaded next to `path` first, and only moved into
+/// place once it's complete, so `path` never contains a partial download.
+/// Interrupted downloads are resumed if the server supports it, and the file
+/// didn't change since. If a `checksum` is given, a download that doesn't
+/// match it is discarded.
+pub async fn download(url: &str, path: &Path, checksum: Option<&Checksum>) -> Result<(), Error> {
+    let part_path = part_path(path);
+    let validator_path = validator_path(path);
+    let resume_from = match tokio::fs::metadata(&part_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+    // Partial downloads can only be resumed if it's known which version of the file they're of
+    let validator = match resume_from {
+        0 => None,
+        _ => tokio::fs::read_to_string(&validator_path).await.ok(),
+    };
+
+    let mut request = HTTP_CLIENT.get(url);
+    if let Some(validator) = &validator {
+        request = request
+            .header(RANGE, format!("bytes={resume_from}-"))
+            .header(IF_RANGE, validator.as_str());
+    }
+    let mut response = request.send().await?;
+    // The partial download is longer than the file, so it must have changed
+    if validator.is_some() && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        response = HTTP_CLIENT.get(url).send().await?;
+    }
+    let mut response = response.error_for_status()?;
+    // If the file changed, or the server doesn't support range requests, it sends the whole file
+    let resumed = validator.is_some()
+        && response.status() == StatusCode::PARTIAL_CONTENT
+        && response.headers().contains_key(CONTENT_RANGE);
+
+    let mut file = if resumed {
+        println!("📦 Resuming download at {resume_from} bytes");
+        tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&part_path)
+            .await?
+    } else {
+        match validator_of(response.headers()) {
+            Some(validator) => tokio::fs::write(&validator_path, validator).await?,
+            None => remove_if_exists(&validator_path).await?,
+        }
+        tokio::fs::File::create(&part_path).await?
+    };
+
+    let progress = match response.content_length() {
+        Some(len) if resumed => ProgressBar::new(resume_from + len),
+        Some(len) => ProgressBar::new(len),
+        None => ProgressBar::no_length(),
+    };
+    if resumed {
+        progress.set_position(resume_from);
+    }
+    progress.set_style(
+        ProgressStyle::with_template(
+            "[{wide_bar:.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )
+        .unwrap()
+        .progress_chars(". "),
+    );
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        progress.inc(chunk.len() as u64);
+    }
+    file.sync_all().await?;
+    drop(file);
+    progress.finish_and_clear();
+
+    if let Some(checksum) = checksum {
+        let expected = checksum.resolve().await?;
+        let actual = tokio::task::spawn_blocking({
+            let part_path = part_path.clone();
+            move || sha256_file(&part_path)
+        })
+        .await
+        .unwrap()?;
+        if actual != expected {
+            // Don't resume from a corrupt download next time
+            tokio::fs::remove_file(&part_path).await?;
+            remove_if_exists(&validator_path).await?;
+            return Err(anyhow::anyhow!(
+                "The database dump downloaded from {url} is corrupt: \
+                expected SHA-256 checksum {expected}, got {actual}"
+            )
+            .into());
+        }
+        println!("🔒 Verified SHA-256 checksum of the downloaded database dump");
+    }
+
+    tokio::fs::rename(&part_path, path).await?;
+    remove_if_exists(&validator_path).await?;
+    Ok(())
+}
+
+/// The validator identifying the version of a file sent with `headers`, to
+/// resume downloading it with `If-Range`: its `ETag`, unless that's weak, as
+/// those can't be used with ranges, or else its `Last-Modified` date
+fn validator_of(headers: &HeaderMap) -> Option<&str> {
+    let etag = headers
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"));
+    etag.or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())
+}
+
+/// Remove the file at `path`, if there is one
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// The path partial downloads for `path` are written to
+fn part_path(path: &Path) -> PathBuf {
+    let mut part_path = path.as_os_str().to_owned();
+    part_path.push(".part");
+    part_path.into()
+}
+
+/// The path the validator of the partial download for `path` is written to
+fn validator_path(path: &Path) -> PathBuf {
+    let mut validator_path = path.as_os_str().to_owned();
+    validator_path.push(".part.validator");
+    validator_path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+
+    use super::{validator_of, Checksum, DbDumpLocation};
+
+    #[test]
+    fn test_db_dump_location() {
+        let location: DbDumpLocation = "./db-dump.tar.gz".parse().unwrap();
+        assert_eq!(location.local_path(), PathBuf::from("./db-dump.tar.gz"));
+
+        let location: DbDumpLocation = "https://example.com/dumps/2025-01-01.tar.gz"
+            .parse()
+            .unwrap();
+        assert!(matches!(location, DbDumpLocation::Url(_)));
+        assert!(location.local_path().ends_with("og-loc/2025-01-01.tar.gz"));
+    }
+
+    #[test]
+    fn test_checksum() {
+        let digest = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        let checksum: Checksum = digest.parse().unwrap();
+        assert_eq!(checksum, Checksum::Sha256(digest.to_ascii_lowercase()));
+
+        let checksum: Checksum = "https://example.com/db-dump.tar.gz.sha256".parse().unwrap();
+        assert!(matches!(checksum, Checksum::Url(_)));
+
+        assert!("9f86d081".parse::<Checksum>().is_err());
+        assert!(digest.replace('F', "g").parse::<Checksum>().is_err());
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test");
+        std::fs::write(&path, "test").unwrap();
+        assert_eq!(
+            super::sha256_file(&path).unwrap(),
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_validator_of() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(validator_of(&headers), None);
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 15 Jan 2025 02:00:01 GMT".parse().unwrap(),
+        );
+        assert_eq!(
+            validator_of(&headers),
+            Some("Wed, 15 Jan 2025 02:00:01 GMT")
+        );
+        // Weak ETags can't be used with `If-Range`
+        headers.insert(ETAG, "W/\"weak\"".parse().unwrap());
+        assert_eq!(
+            validator_of(&headers),
+            Some("Wed, 15 Jan 2025 02:00:01 GMT")
+        );
+        headers.insert(ETAG, "\"strong\"".parse().unwrap());
+        assert_eq!(validator_of(&headers), Some("\"strong\""));
+    }
+}
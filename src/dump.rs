@@ -0,0 +1,570 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use csv::ByteRecord;
+use db_dump::{crates::CrateId, versions::VersionId};
+use flate2::read::GzDecoder;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::ThreadPool;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::error::Error;
+
+/// The formats a database dump can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The `.tar.gz` archive as published by crates.io
+    TarGz,
+    /// A recompressed `.tar.zst` archive
+    TarZst,
+    /// A directory containing the extracted CSVs
+    Dir,
+}
+
+impl DumpFormat {
+    /// Guess the format of the dump at `path`. Anything that's not
+    /// a directory or a zstd archive is assumed to be a `.tar.gz`.
+    fn detect(path: &Path) -> Self {
+        if path.is_dir() {
+            return Self::Dir;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            return Self::TarZst;
+        }
+        Self::TarGz
+    }
+}
+
+/// A crates.io database dump, read one table at a time.
+#[derive(Debug)]
+pub struct DbDump {
+    path: PathBuf,
+    format: DumpFormat,
+    stats: RefCell<Vec<TableStats>>,
+    /// The pool to deserialize rows on, instead of the global rayon pool
+    pool: Option<Arc<ThreadPool>>,
+}
+
+/// Statistics on loading a single table
+#[derive(Debug)]
+struct TableStats {
+    table: String,
+    rows: usize,
+    duration: Duration,
+}
+
+impl DbDump {
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let format = DumpFormat::detect(&path);
+        Self {
+            path,
+            format,
+            stats: RefCell::default(),
+            pool: None,
+        }
+    }
+
+    /// Deserialize rows on `pool`, instead of the global rayon pool
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Print the number of rows and time taken for each table loaded so far
+    pub fn print_stats(&self) {
+        let stats = self.stats.borrow();
+        println!(
+            "📊 Loaded {} tables from {}:",
+            stats.len(),
+            self.path.display()
+        );
+        for TableStats {
+            table,
+            rows,
+            duration,
+        } in stats.iter()
+        {
+            println!("{table:>24}: {rows:>10} rows in {duration:.2?}");
+        }
+        let total: Duration = stats.iter().map(|s| s.duration).sum();
+        println!("{:>24}: {total:.2?}", "total");
+    }
+
+    /// A fingerprint of the dump, based on the names, sizes and
+    /// modification times of its files. Much cheaper than hashing
+    /// the contents, which can be several gigabytes.
+    pub fn fingerprint(&self) -> Result<u64, Error> {
+        let mut hasher = DefaultHasher::new();
+        let mut paths = vec![self.path.clone()];
+        while let Some(path) = paths.pop() {
+            let metadata = std::fs::metadata(&path)?;
+            if metadata.is_dir() {
+                for entry in std::fs::read_dir(&path)? {
+                    paths.push(entry?.path());
+                }
+                // Make sure the order of directory entries doesn't matter
+                paths.sort();
+                continue;
+            }
+            path.hash(&mut hasher);
+            metadata.len().hash(&mut hasher);
+            metadata.modified()?.hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Read the dump's `metadata.json`, which contains the time of the export.
+    /// For archives, this is one of the first files, so this is cheap.
+    pub fn metadata(&self) -> Result<DumpMetadata, Error> {
+        let read_json = |input: &mut dyn Read| {
+            serde_json::from_reader(input).map_err(|e| anyhow::anyhow!(e).into())
+        };
+        match self.format {
+            DumpFormat::Dir => read_json(&mut File::open(find_file(&self.path, METADATA_FILE)?)?),
+            DumpFormat::TarGz => find_in_archive(
+                GzDecoder::new(BufReader::new(File::open(&self.path)?)),
+                METADATA_FILE,
+                read_json,
+            ),
+            DumpFormat::TarZst => find_in_archive(
+                zstd::Decoder::new(File::open(&self.path)?)?,
+                METADATA_FILE,
+                read_json,
+            ),
+        }
+    }
+
+    /// Call `f` for each row of `table`.
+    /// For archives, this inflates the archive up to the table's CSV.
+    pub fn load<T: DeserializeOwned + Send + 'static>(
+        &self,
+        table: &str,
+        mut f: impl FnMut(T),
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+        let mut rows = 0;
+        let f = |row| {
+            rows += 1;
+            LOAD_PROGRESS.rows.fetch_add(1, Ordering::Relaxed);
+            f(row)
+        };
+        let file_name = format!("{table}.csv");
+        let path = match self.format {
+            DumpFormat::Dir => find_file(&self.path, &file_name)?,
+            DumpFormat::TarGz | DumpFormat::TarZst => self.path.clone(),
+        };
+
+        let progress = progress_bar(&path)?;
+        LOAD_PROGRESS.start_table(progress.length().unwrap_or_default());
+        progress.set_message(LOAD_PROGRESS.message(table));
+        let input = ProgressReader {
+            inner: BufReader::new(File::open(&path)?),
+            progress: &progress,
+        };
+        let pool = self.pool.as_deref();
+        let result = match self.format {
+            DumpFormat::TarGz => {
+                find_in_archive(GzDecoder::new(input), &file_name, |e| read_csv(e, pool, f))
+            }
+            DumpFormat::TarZst => find_in_archive(zstd::Decoder::new(input)?, &file_name, |e| {
+                read_csv(e, pool, f)
+            }),
+            DumpFormat::Dir => read_csv(input, pool, f),
+        };
+        progress.finish_and_clear();
+        LOAD_PROGRESS.finish_table();
+
+        self.stats.borrow_mut().push(TableStats {
+            table: table.to_string(),
+            rows,
+            duration: start.elapsed(),
+        });
+        result
+    }
+}
+
+/// Progress of loading a database dump, for reporting
+/// by the server while it's starting up.
+#[derive(Debug)]
+pub struct LoadProgress {
+    tables_total: AtomicUsize,
+    tables_done: AtomicUsize,
+    table_bytes_total: AtomicU64,
+    table_bytes_read: AtomicU64,
+    rows: AtomicU64,
+}
+
+/// The progress of the database dump currently being loaded
+pub static LOAD_PROGRESS: LoadProgress = LoadProgress {
+    tables_total: AtomicUsize::new(0),
+    tables_done: AtomicUsize::new(0),
+    table_bytes_total: AtomicU64::new(0),
+    table_bytes_read: AtomicU64::new(0),
+    rows: AtomicU64::new(0),
+};
+
+impl LoadProgress {
+    /// Reset the progress, announcing that `tables` tables will be loaded
+    pub fn start(&self, tables: usize) {
+        self.tables_total.store(tables, Ordering::Relaxed);
+        self.tables_done.store(0, Ordering::Relaxed);
+        self.rows.store(0, Ordering::Relaxed);
+    }
+
+    fn start_table(&self, bytes_total: u64) {
+        self.table_bytes_total.store(bytes_total, Ordering::Relaxed);
+        self.table_bytes_read.store(0, Ordering::Relaxed);
+    }
+
+    fn finish_table(&self) {
+        self.tables_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn message(&self, table: &str) -> String {
+        let total = self.tables_total.load(Ordering::Relaxed);
+        let current = self.tables_done.load(Ordering::Relaxed) + 1;
+        if current > total {
+            return table.to_string();
+        }
+        format!("{table} ({current}/{total})")
+    }
+
+    /// The fraction of the dump that was loaded, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        let total = self.tables_total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.;
+        }
+        let done = self.tables_done.load(Ordering::Relaxed).min(total);
+        let bytes_total = self.table_bytes_total.load(Ordering::Relaxed);
+        let table_fraction = if done < total && bytes_total > 0 {
+            self.table_bytes_read.load(Ordering::Relaxed) as f64 / bytes_total as f64
+        } else {
+            0.
+        };
+        (done as f64 + table_fraction.min(1.)) / total as f64
+    }
+
+    /// The number of rows processed so far
+    pub fn rows(&self) -> u64 {
+        self.rows.load(Ordering::Relaxed)
+    }
+}
+
+/// Reports the bytes read from the dump to both a progress
+/// bar and [`LOAD_PROGRESS`].
+struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a ProgressBar,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.progress.inc(n as u64);
+        LOAD_PROGRESS
+            .table_bytes_read
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+fn progress_bar(path: &Path) -> Result<ProgressBar, Error> {
+    let progress = ProgressBar::with_draw_target(
+        Some(std::fs::metadata(path)?.len()),
+        ProgressDrawTarget::stderr(),
+    );
+    progress.set_style(
+        ProgressStyle::with_template("[{wide_bar:.cyan/blue}] {percent}% {msg:>24}")
+            .unwrap()
+            .progress_chars(". "),
+    );
+    Ok(progress)
+}
+
+/// The name of the file containing the [`DumpMetadata`]
+const METADATA_FILE: &str = "metadata.json";
+
+/// The contents of the dump's `metadata.json`
+#[derive(Debug, Deserialize)]
+pub struct DumpMetadata {
+    /// When the dump was exported
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Call `f` with the first entry of the archive named `file_name`
+fn find_in_archive<T>(
+    input: impl Read,
+    file_name: &str,
+    f: impl FnOnce(&mut dyn Read) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let mut archive = tar::Archive::new(input);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
+        if path.file_name().is_some_and(|name| name == file_name) {
+            return f(&mut entry);
+        }
+    }
+    Err(missing_file(file_name))
+}
+
+/// Find a file in an extracted dump. Accepts the directory containing the
+/// CSVs, its parent, or the directory the archive was extracted into.
+fn find_file(dir: &Path, file_name: &str) -> Result<PathBuf, Error> {
+    let mut candidates = vec![dir.join(file_name), dir.join("data").join(file_name)];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            candidates.push(entry.path().join(file_name));
+            candidates.push(entry.path().join("data").join(file_name));
+        }
+    }
+    candidates
+        .into_iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| missing_file(file_name))
+}
+
+fn missing_file(file_name: &str) -> Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{file_name} not found in database dump"),
+    )
+    .into()
+}
+
+/// The number of rows deserialized per batch in [`read_csv`]
+const BATCH_ROWS: usize = 8192;
+
+/// Call `f` for each row of the CSV in `input`, in order. Only splitting the input
+/// into records happens on the current thread: rows are deserialized in batches
+/// on `pool`, or the global rayon pool, while the next batch is being read.
+fn read_csv<T: DeserializeOwned + Send + 'static>(
+    input: impl Read,
+    pool: Option<&ThreadPool>,
+    mut f: impl FnMut(T),
+) -> Result<(), Error> {
+    let mut reader = csv::Reader::from_reader(input);
+    let headers = Arc::new(reader.byte_headers()?.clone());
+    let mut records = reader.into_byte_records();
+    // Limit the number of batches in flight, so memory use stays bounded
+    // if `f` or deserialization can't keep up with reading
+    let max_pending = pool.map_or_else(rayon::current_num_threads, |pool| {
+        pool.current_num_threads()
+    }) * 2;
+    let mut pending = VecDeque::with_capacity(max_pending);
+    loop {
+        let batch: Vec<ByteRecord> = records
+            .by_ref()
+            .take(BATCH_ROWS)
+            .collect::<Result<_, _>>()?;
+        let done = batch.len() < BATCH_ROWS;
+        if !batch.is_empty() {
+            let (tx, rx) = mpsc::sync_channel(1);
+            let headers = headers.clone();
+            let deserialize = move || {
+                let rows: Result<Vec<T>, _> = batch
+                    .iter()
+                    .map(|record| record.deserialize(Some(&headers)))
+                    .collect();
+                // The receiver is gone if an earlier batch failed
+                let _ = tx.send(rows);
+            };
+            match pool {
+                Some(pool) => pool.spawn(deserialize),
+                None => rayon::spawn(deserialize),
+            }
+            pending.push_back(rx);
+        }
+        while pending.len() >= max_pending || (done && !pending.is_empty()) {
+            let rows = pending.pop_front().unwrap().recv().unwrap()?;
+            rows.into_iter().for_each(&mut f);
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+/// The parts of a row of `crate_owners.csv` we're interested in.
+/// Unlike most tables, [`db_dump`] doesn't expose a way to deserialize these.
+#[derive(Debug, Deserialize)]
+pub struct CrateOwnerRow {
+    pub crate_id: CrateId,
+    pub owner_id: u32,
+    pub owner_kind: u8,
+}
+
+impl CrateOwnerRow {
+    pub fn owner_id(&self) -> Option<db_dump::crate_owners::OwnerId> {
+        use db_dump::{crate_owners::OwnerId, teams::TeamId, users::UserId};
+        match self.owner_kind {
+            0 => Some(OwnerId::User(UserId(self.owner_id))),
+            1 => Some(OwnerId::Team(TeamId(self.owner_id))),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of a row of `crates.csv` we're interested in. Unlike
+/// [`db_dump::crates::Row`], this skips the crates' READMEs.
+#[derive(Debug, Deserialize)]
+pub struct CrateRow {
+    pub id: CrateId,
+    pub name: String,
+    pub description: String,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    #[serde(deserialize_with = "datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(deserialize_with = "datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The parts of a row of `versions.csv` we're interested in
+#[derive(Debug, Deserialize)]
+pub struct VersionRow {
+    pub id: VersionId,
+    pub crate_id: CrateId,
+    pub num: String,
+    pub license: Option<String>,
+    pub rust_version: Option<String>,
+    pub crate_size: Option<u64>,
+    #[serde(deserialize_with = "datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(deserialize_with = "bool")]
+    pub yanked: bool,
+}
+
+/// Deserialize a boolean in the format used by the database dump, `t` or `f`
+fn bool<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match &*String::deserialize(deserializer)? {
+        "t" => Ok(true),
+        "f" => Ok(false),
+        other => Err(serde::de::Error::unknown_variant(other, &["t", "f"])),
+    }
+}
+
+/// Deserialize a timestamp in the format used by the database dump, e.g.
+/// `2025-01-01 00:00:00.000000`, optionally followed by a `+00` offset.
+/// The timestamps are in UTC.
+fn datetime<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    let s = s.strip_suffix("+00").unwrap_or(&s);
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|datetime| datetime.and_utc())
+        .map_err(serde::de::Error::custom)
+}
+
+/// The parts of a row of `dependencies.csv` we're interested in
+#[derive(Debug, Deserialize)]
+pub struct DependencyRow {
+    /// The version declaring the dependency
+    pub version_id: VersionId,
+    /// The crate depended on
+    pub crate_id: CrateId,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use test_case::case;
+
+    use super::{CrateOwnerRow, DbDump, DumpFormat, VersionRow};
+
+    #[case("db-dump.tar.gz" => DumpFormat::TarGz)]
+    #[case("db-dump.tar.zst" => DumpFormat::TarZst)]
+    #[case("src" => DumpFormat::Dir)]
+    fn test_detect_format(path: &str) -> DumpFormat {
+        DumpFormat::detect(Path::new(path))
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("2025-01-01-020000").join("data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(
+            data_dir.join("crate_owners.csv"),
+            "crate_id,created_at,created_by,owner_id,owner_kind\n\
+            1,2025-01-01 00:00:00.000000+00,,2,0\n\
+            1,2025-01-01 00:00:00.000000+00,,3,1\n",
+        )
+        .unwrap();
+
+        let mut rows = vec![];
+        DbDump::open(dir.path())
+            .load("crate_owners", |row: CrateOwnerRow| {
+                rows.push(row.owner_id())
+            })
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(DbDump::open(dir.path())
+            .load("crates", |_: CrateOwnerRow| {})
+            .is_err());
+
+        assert!(DbDump::open(dir.path()).metadata().is_err());
+        std::fs::write(
+            data_dir.parent().unwrap().join("metadata.json"),
+            r#"{"timestamp":"2025-01-01T02:00:01Z","crates_io_commit":"abc"}"#,
+        )
+        .unwrap();
+        let metadata = DbDump::open(dir.path()).metadata().unwrap();
+        assert_eq!(metadata.timestamp.to_rfc3339(), "2025-01-01T02:00:01+00:00");
+    }
+
+    #[case("2025-01-01 02:00:01.123456" => "2025-01-01T02:00:01.123456+00:00")]
+    #[case("2025-01-01 02:00:01.123456+00" => "2025-01-01T02:00:01.123456+00:00")]
+    #[case("2025-01-01 02:00:01" => "2025-01-01T02:00:01+00:00")]
+    fn test_version_created_at(created_at: &str) -> String {
+        let csv = format!(
+            "id,crate_id,num,license,rust_version,crate_size,created_at,yanked\n\
+            1,2,1.0.0,MIT,,,{created_at},t\n"
+        );
+        let mut rows = vec![];
+        super::read_csv(csv.as_bytes(), None, |row: VersionRow| rows.push(row)).unwrap();
+        assert!(rows[0].yanked);
+        rows[0].created_at.to_rfc3339()
+    }
+
+    #[test]
+    fn test_read_csv_batches() {
+        let rows = super::BATCH_ROWS * 5 + 3;
+        let mut csv = String::from("crate_id,owner_id,owner_kind\n");
+        for i in 0..rows {
+            csv.push_str(&format!("{i},{i},0\n"));
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        for pool in [None, Some(&pool)] {
+            let mut ids = vec![];
+            super::read_csv(csv.as_bytes(), pool, |row: CrateOwnerRow| {
+                ids.push(row.owner_id)
+            })
+            .unwrap();
+            assert_eq!(ids, (0..rows as u32).collect::<Vec<_>>());
+        }
+
+        csv.push_str("not,a,number\n");
+        assert!(super::read_csv(csv.as_bytes(), None, |_: CrateOwnerRow| {}).is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::process::ExitCode;
+
 use axum::{http::StatusCode, response::IntoResponse};
 
 #[derive(Debug, thiserror::Error)]
@@ -12,11 +14,26 @@ pub enum Error {
     Io(#[from] std::io::Error),
 
     #[error("DB dump load error: {0}")]
-    DbDump(#[from] db_dump::Error),
+    DbDump(#[from] csv::Error),
+
+    #[error("Template error: {0}")]
+    Template(#[from] minijinja::Error),
+
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Render error: {0}")]
+    Render(#[from] crate::convert::RenderError),
 
     #[error("Error: {0}")]
     Other(#[from] anyhow::Error),
 
+    #[error("The database dump is still being loaded")]
+    NotReady,
+
+    #[error("Index error: {0}")]
+    Index(Box<redb::Error>),
+
     #[error("Buld input error: {0}")]
     BulkInput(#[from] crate::bulk::BulkInputError),
 }
@@ -30,10 +47,33 @@ impl Error {
             Error::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::BulkInput(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::DbDump(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Template(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::Http(_) => StatusCode::BAD_GATEWAY,
+            Error::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::NotReady => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Index(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The exit code of the process when a command fails with this error:
+    /// 2 if the crate wasn't found, 3 if a crate name is invalid, and 1 otherwise
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Error::NotFound => EXIT_NOT_FOUND.into(),
+            Error::InvalidCrateName(_)
+            | Error::BulkInput(crate::bulk::BulkInputError::InvalidCrateName(..)) => {
+                EXIT_INVALID_CRATE_NAME.into()
+            }
+            _ => ExitCode::FAILURE,
         }
     }
 }
 
+/// The exit code for when the requested crate wasn't found
+pub const EXIT_NOT_FOUND: u8 = 2;
+/// The exit code for invalid crate names
+pub const EXIT_INVALID_CRATE_NAME: u8 = 3;
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         (self.status_code(), self.to_string()).into_response()
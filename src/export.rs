@@ -0,0 +1,243 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    augment::DataFilter,
+    bulk::{augment_all, load_crates, load_filter, BulkInput},
+    convert::CrateData,
+    error::Error,
+    CommonArgs,
+};
+
+#[derive(Debug, clap::Args)]
+pub struct Export {
+    /// Input specifier, like that of `bulk`. Exports every crate in the database dump if omitted
+    #[arg(env, long = "in", short)]
+    pub input: Option<BulkInput>,
+    /// Export all crates in the database dump whose name matches this
+    /// regular expression, instead of reading crate names from `--in`
+    #[arg(env, long, conflicts_with = "input")]
+    pub filter_regex: Option<regex::Regex>,
+    /// Comma-separated list of category slugs. Only crates in one
+    /// of these categories or their subcategories are exported
+    #[arg(env, long, value_delimiter = ',')]
+    pub category: Vec<String>,
+    /// Comma-separated list of keywords. Only crates with one of these keywords are exported
+    #[arg(env, long, value_delimiter = ',')]
+    pub keyword: Vec<String>,
+    /// Only export crates with at least this many all-time downloads
+    #[arg(env, long, default_value_t = 0)]
+    pub min_downloads: u64,
+    /// Only export this many of the most downloaded crates
+    #[arg(env, long)]
+    pub top: Option<usize>,
+    /// The order to write the crates in
+    #[arg(env, long, value_enum, default_value = "name")]
+    pub sort: ExportSort,
+    /// The path of the file to write the crate data to
+    #[arg(env, long = "out", short)]
+    pub out_path: PathBuf,
+    /// The format to write. Defaults to CSV for `.csv` files, and JSON Lines otherwise
+    #[arg(env, long, value_enum)]
+    pub format: Option<ExportFormat>,
+}
+
+/// The order of exported crates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportSort {
+    /// Alphabetically by name
+    Name,
+    /// The most downloaded crates first
+    Downloads,
+}
+
+/// The format of exported crate data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// One JSON object per line, with the same fields as passed to the template
+    Jsonl,
+    /// One row per crate, with lists joined by commas
+    Csv,
+}
+
+impl Export {
+    pub async fn run(self, common: CommonArgs) -> Result<(), Error> {
+        let data_filter = DataFilter {
+            categories: self.category,
+            keywords: self.keyword,
+            min_downloads: self.min_downloads,
+        };
+        let (filter, _) = load_filter(self.input, self.filter_regex, false).await?;
+        let format = self.format.unwrap_or_else(|| {
+            match self.out_path.extension().and_then(|ext| ext.to_str()) {
+                Some("csv") => ExportFormat::Csv,
+                _ => ExportFormat::Jsonl,
+            }
+        });
+
+        let (db, crates) = load_crates(&common, filter, &data_filter, self.top).await?;
+        let mut crates = augment_all(db, crates).await;
+        match self.sort {
+            ExportSort::Name => {
+                crates.sort_unstable_by(|a, b| a.name.as_ref().cmp(b.name.as_ref()))
+            }
+            ExportSort::Downloads => crates.sort_unstable_by(|a, b| {
+                b.downloads
+                    .cmp(&a.downloads)
+                    .then_with(|| a.name.as_ref().cmp(b.name.as_ref()))
+            }),
+        }
+        let count = crates.len();
+        let out_path = self.out_path;
+        tokio::task::spawn_blocking({
+            let out_path = out_path.clone();
+            move || write_crates(&crates, File::create(out_path)?, format)
+        })
+        .await
+        .unwrap()?;
+        println!("📤 Exported {count} crates to {}", out_path.display());
+        Ok(())
+    }
+}
+
+/// Write `crates` to `out` in the passed format
+fn write_crates(crates: &[CrateData], out: impl Write, format: ExportFormat) -> Result<(), Error> {
+    let mut out = BufWriter::new(out);
+    match format {
+        ExportFormat::Jsonl => {
+            for data in crates {
+                serde_json::to_writer(&mut out, data).map_err(anyhow::Error::from)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            for data in crates {
+                writer.serialize(CsvRow::from(data))?;
+            }
+            writer.flush()?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// A row of a CSV export. CSV can't contain nested data,
+/// so lists are joined by commas, and owners by their names.
+#[derive(serde::Serialize)]
+struct CsvRow<'a> {
+    name: &'a str,
+    description: &'a str,
+    version: Option<&'a str>,
+    license: Option<&'a str>,
+    rust_version: Option<&'a str>,
+    crate_size: Option<u64>,
+    published_at: Option<DateTime<Utc>>,
+    num_versions: u32,
+    num_owners: usize,
+    user_owners: String,
+    team_owners: String,
+    downloads: u64,
+    recent_downloads: u64,
+    weekly_downloads: String,
+    dependents: u64,
+    keywords: String,
+    categories: String,
+    repository: Option<&'a str>,
+    homepage: Option<&'a str>,
+    documentation: Option<&'a str>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl<'a> From<&'a CrateData> for CsvRow<'a> {
+    fn from(data: &'a CrateData) -> Self {
+        fn join<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+            items.iter().map(f).collect::<Vec<_>>().join(",")
+        }
+
+        Self {
+            name: data.name.as_ref(),
+            description: &data.description,
+            version: data.version.as_deref(),
+            license: data.license.as_deref(),
+            rust_version: data.rust_version.as_deref(),
+            crate_size: data.crate_size,
+            published_at: data.published_at,
+            num_versions: data.num_versions,
+            num_owners: data.num_owners,
+            user_owners: join(&data.user_owners, |o| o.login.clone()),
+            team_owners: join(&data.team_owners, |o| o.name.clone()),
+            downloads: data.downloads,
+            recent_downloads: data.recent_downloads,
+            weekly_downloads: join(&data.weekly_downloads, ToString::to_string),
+            dependents: data.dependents,
+            keywords: data.keywords.join(","),
+            categories: join(&data.categories, |c| c.slug.clone()),
+            repository: data.repository.as_deref(),
+            homepage: data.homepage.as_deref(),
+            documentation: data.documentation.as_deref(),
+            created_at: data.created_at,
+            updated_at: data.updated_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_crates, ExportFormat};
+    use crate::convert::{CrateData, UserCrateOwner};
+
+    #[test]
+    fn test_write_crates() {
+        let data = CrateData {
+            name: "internal".parse().unwrap(),
+            description: "An internal crate".into(),
+            team_owners: vec![],
+            user_owners: ["alice", "bob"]
+                .map(|login| UserCrateOwner {
+                    avatar: String::new(),
+                    login: login.into(),
+                    name: None,
+                    url: format!("https://github.com/{login}"),
+                })
+                .into(),
+            weekly_downloads: vec![1, 2],
+            license: None,
+            version: Some("1.0.0".into()),
+            rust_version: None,
+            crate_size: None,
+            published_at: None,
+            num_versions: 1,
+            num_owners: 2,
+            downloads: 3,
+            recent_downloads: 3,
+            dependents: 0,
+            keywords: vec!["auth".into(), "web".into()],
+            categories: vec![],
+            repository: None,
+            homepage: None,
+            documentation: None,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+        };
+
+        let mut jsonl = vec![];
+        write_crates(std::slice::from_ref(&data), &mut jsonl, ExportFormat::Jsonl).unwrap();
+        let parsed: CrateData = serde_json::from_slice(&jsonl).unwrap();
+        assert_eq!(parsed, data);
+
+        let mut csv = vec![];
+        write_crates(&[data], &mut csv, ExportFormat::Csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with(
+            "internal,An internal crate,1.0.0,,,,,1,2,\"alice,bob\",,3,3,\"1,2\",0,\"auth,web\""
+        ));
+    }
+}
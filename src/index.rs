@@ -0,0 +1,230 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use redb::{Database, ReadableTableMetadata, TableDefinition};
+
+use crate::{
+    augment::{api_fallback_enabled, normalize_crate_name, CrateDb},
+    convert::CrateData,
+    crates_io,
+    error::Error,
+    source::CrateDataSource,
+    spec::CrateName,
+};
+
+/// Augmented crate data, encoded using bincode, by crate name
+const CRATES: TableDefinition<&str, &[u8]> = TableDefinition::new("crates");
+/// Crate names by their [normalized](normalize_crate_name) form
+const NORMALIZED_NAMES: TableDefinition<&str, &str> = TableDefinition::new("normalized_names");
+/// The key the index was built for, and the timestamp of the dump
+const METADATA: TableDefinition<&str, i64> = TableDefinition::new("metadata");
+
+// The specific errors of redb all convert into `redb::Error`, which
+// is boxed as it's much larger than the other variants of `Error`
+macro_rules! impl_from_redb_error {
+    ($($error:ty),*) => {
+        $(impl From<$error> for Error {
+            fn from(e: $error) -> Self {
+                Error::Index(Box::new(e.into()))
+            }
+        })*
+    };
+}
+
+impl_from_redb_error!(
+    redb::Error,
+    redb::DatabaseError,
+    redb::TransactionError,
+    redb::TableError,
+    redb::StorageError,
+    redb::CommitError
+);
+
+/// The amount of memory redb may use to cache pages of the index
+const PAGE_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// An on-disk index of augmented crate data, built once from a [`CrateDb`].
+/// Lookups are slightly slower than those of a [`CrateDb`], but only the
+/// pages of the index that are used are kept in memory, so large deployments
+/// can serve every crate with a fraction of the memory.
+pub struct CrateIndex {
+    db: Database,
+    key: u64,
+    dump_timestamp: Option<DateTime<Utc>>,
+}
+
+impl CrateIndex {
+    /// Write every crate in `db` to a new index at `path`, replacing any
+    /// existing index. The `key` identifies the data the index was built
+    /// from, so stale indexes can be detected using [`Self::key`].
+    pub fn build(db: &CrateDb, path: &Path, key: u64) -> Result<Self, Error> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let index = Database::builder()
+            .set_cache_size(PAGE_CACHE_BYTES)
+            .create(path)?;
+        let txn = index.begin_write()?;
+        {
+            let mut crates = txn.open_table(CRATES)?;
+            let mut normalized_names = txn.open_table(NORMALIZED_NAMES)?;
+            for data in db.augment_preloaded() {
+                let data = match data {
+                    Ok(data) => data,
+                    Err(e) => {
+                        eprintln!("Skipping crate that could not be augmented: {e}");
+                        continue;
+                    }
+                };
+                let name = data.name.as_ref();
+                let encoded = bincode::serialize(&data).map_err(|e| anyhow::anyhow!(e))?;
+                crates.insert(name, encoded.as_slice())?;
+                normalized_names.insert(normalize_crate_name(name).as_str(), name)?;
+            }
+            let mut metadata = txn.open_table(METADATA)?;
+            metadata.insert("key", i64::from_ne_bytes(key.to_ne_bytes()))?;
+            if let Some(timestamp) = db.dump_timestamp() {
+                metadata.insert("dump_timestamp", timestamp.timestamp())?;
+            }
+        }
+        txn.commit()?;
+        Ok(Self {
+            db: index,
+            key,
+            dump_timestamp: db.dump_timestamp(),
+        })
+    }
+
+    /// Open an existing index at `path`
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let db = Database::builder()
+            .set_cache_size(PAGE_CACHE_BYTES)
+            .open(path)?;
+        let (key, dump_timestamp) = {
+            let txn = db.begin_read()?;
+            let metadata = txn.open_table(METADATA)?;
+            let get =
+                |name| -> Result<Option<i64>, Error> { Ok(metadata.get(name)?.map(|v| v.value())) };
+            let key = get("key")?.ok_or_else(|| anyhow::anyhow!("Index has no key"))?;
+            let dump_timestamp =
+                get("dump_timestamp")?.and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+            (u64::from_ne_bytes(key.to_ne_bytes()), dump_timestamp)
+        };
+        Ok(Self {
+            db,
+            key,
+            dump_timestamp,
+        })
+    }
+
+    /// The key passed to [`Self::build`]
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// When the database dump the index was built from was exported, if known
+    pub fn dump_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.dump_timestamp
+    }
+
+    /// The number of crates in the index
+    pub fn len(&self) -> Result<u64, Error> {
+        let txn = self.db.begin_read()?;
+        let crates = txn.open_table(CRATES)?;
+        Ok(crates.len()?)
+    }
+
+    /// Whether the index contains no crates
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Whether the index has a crate called `name`, or one whose normalized name is the same
+    pub fn contains(&self, name: &str) -> Result<bool, Error> {
+        let txn = self.db.begin_read()?;
+        let normalized_names = txn.open_table(NORMALIZED_NAMES)?;
+        Ok(normalized_names
+            .get(normalize_crate_name(name).as_str())?
+            .is_some())
+    }
+
+    /// The data of the crate called `name`, looked up like [`CrateDb`] does:
+    /// by its exact name, or else by its normalized name.
+    pub fn augment_crate_spec(&self, name: CrateName) -> Result<CrateData, Error> {
+        let txn = self.db.begin_read()?;
+        let crates = txn.open_table(CRATES)?;
+        let mut encoded = crates.get(name.as_ref())?;
+        if encoded.is_none() {
+            let normalized_names = txn.open_table(NORMALIZED_NAMES)?;
+            let actual_name = normalized_names.get(normalize_crate_name(name.as_ref()).as_str())?;
+            if let Some(actual_name) = actual_name {
+                encoded = crates.get(actual_name.value())?;
+            }
+        }
+        let encoded = encoded.ok_or(Error::NotFound)?;
+        Ok(bincode::deserialize(encoded.value()).map_err(|e| anyhow::anyhow!(e))?)
+    }
+}
+
+/// Crates in the index. Fetches missing crates from the crates.io API if
+/// enabled using [`enable_api_fallback`](crate::augment::enable_api_fallback).
+impl CrateDataSource for CrateIndex {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        match self.augment_crate_spec(name.clone()) {
+            Err(Error::NotFound) if api_fallback_enabled() => {
+                crates_io::fetch_crate_data(name).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrateIndex;
+    use crate::{augment::CrateDb, error::Error, supplement::SupplementaryCrate};
+
+    #[test]
+    fn test_index_roundtrip() {
+        let mut db = CrateDb::empty();
+        db.merge(["Foo-Bar", "baz"].map(|name| SupplementaryCrate {
+            name: name.parse().unwrap(),
+            description: format!("The {name} crate"),
+            owners: vec!["alice".into()],
+            version: Some("1.0.0".into()),
+            license: Some("MIT".into()),
+            downloads: 10,
+            recent_downloads: 5,
+            keywords: vec!["test".into()],
+            repository: None,
+            homepage: None,
+            documentation: None,
+            created_at: None,
+            updated_at: None,
+        }));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.redb");
+        CrateIndex::build(&db, &path, 42).unwrap();
+        let index = CrateIndex::open(&path).unwrap();
+        assert_eq!(index.key(), 42);
+        assert_eq!(index.len().unwrap(), 2);
+
+        let name = "Foo-Bar".parse().unwrap();
+        assert_eq!(
+            index.augment_crate_spec(name).unwrap(),
+            db.augment_crate_spec("Foo-Bar".parse().unwrap()).unwrap()
+        );
+        let data = index
+            .augment_crate_spec("foo_bar".parse().unwrap())
+            .unwrap();
+        assert_eq!(data.name.as_ref(), "Foo-Bar");
+        assert!(index.contains("foo_bar").unwrap());
+        assert!(!index.contains("qux").unwrap());
+        assert!(matches!(
+            index.augment_crate_spec("qux".parse().unwrap()),
+            Err(Error::NotFound)
+        ));
+    }
+}
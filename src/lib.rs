@@ -1,16 +1,39 @@
-use std::{path::PathBuf, sync::LazyLock};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::LazyLock,
+    time::Duration,
+};
 
+use chrono::{DateTime, Utc};
+
+use augment::{CrateDb, LoadFilter, LoadOptions};
 use bulk::Bulk;
+use download::DbDumpLocation;
+use dump::DbDump;
 use error::Error;
+use export::Export;
 use one_shot::OneShot;
 use serve::Serve;
+use template::{TemplateLoader, TemplateSource};
 
+pub mod archive;
 pub mod augment;
 pub mod convert;
+pub mod crates_io;
+pub mod download;
+pub mod dump;
 pub mod error;
+pub mod index;
+pub mod object_store;
+pub mod package;
+pub mod source;
 pub mod spec;
+pub mod supplement;
+pub mod template;
 
 pub mod bulk;
+pub mod export;
 pub mod one_shot;
 pub mod serve;
 
@@ -23,23 +46,253 @@ pub struct Cli {
 }
 
 impl Cli {
-    pub async fn run(self) -> Result<(), Error> {
+    pub async fn run(mut self) -> Result<(), Error> {
+        if let Command::OneShot(OneShot { online: true, .. }) = self.command {
+            self.common.source = DataSource::Api;
+        }
+        match self.common.source {
+            DataSource::Dump => self.common.ensure_db_dump().await?,
+            DataSource::Api => augment::enable_api_fallback(),
+        }
+        if let Some(cache_dir) = &self.common.db_cache_dir {
+            augment::set_cache_dir(cache_dir)?;
+        }
+        if self.common.api_fallback {
+            augment::enable_api_fallback();
+        }
+        if self.common.load_stats {
+            augment::enable_load_stats();
+        }
+        if let Some(asset_dir) = &self.common.asset_dir {
+            convert::set_asset_dir(asset_dir)?;
+        }
+        if let Some(background) = &self.common.background {
+            convert::load_background(background).await?;
+        }
         match self.command {
             Command::Serve(serve) => serve.run(self.common).await,
             Command::OneShot(one_shot) => one_shot.run(self.common).await,
             Command::Bulk(bulk) => bulk.run(self.common).await,
+            Command::Export(export) => export.run(self.common).await,
         }
     }
 }
 
-#[derive(Debug, clap::Args)]
+#[derive(Debug, Clone, clap::Args)]
 pub struct CommonArgs {
-    /// The path of the database dump
+    /// The path of the database dump. Can be a `.tar.gz` or `.tar.zst` archive,
+    /// or a directory containing the extracted CSVs. Can also be an HTTP(S) URL,
+    /// in which case the dump is downloaded to a cache directory before loading
     #[arg(short, long, env, default_value = "./db-dump.tar.gz")]
-    db_dump_path: PathBuf,
+    db_dump_path: DbDumpLocation,
+    /// Where to get crate data from
+    #[arg(long, env, value_enum, default_value = "dump")]
+    source: DataSource,
+    /// Download the latest database dump if it's missing or stale
+    #[arg(long, env)]
+    auto_download: bool,
+    /// The age after which a database dump is considered stale when using
+    /// `--auto-download` or a URL as `--db-dump-path`, e.g. `12h` or `2d`
+    #[arg(long, env, default_value = "1d", value_parser = parse_duration)]
+    auto_download_max_age: Duration,
+    /// Verify downloaded database dumps against this SHA-256 checksum, given either
+    /// as a hex digest or as the HTTP(S) URL of a checksum file. Corrupt downloads are refused
+    #[arg(long, env)]
+    db_dump_sha256: Option<download::Checksum>,
+    /// Directory to cache the parsed database dump in. Loading the full dump,
+    /// as done by `serve`, writes a cache that makes subsequent startups much faster
+    #[arg(long, env)]
+    db_cache_dir: Option<PathBuf>,
+    /// Fetch crates that are missing from the database dump from the crates.io API.
+    /// Useful for newly published crates, which may not be in the dump yet
+    #[arg(long, env)]
+    api_fallback: bool,
+    /// Warn if the loaded database dump was exported longer ago than this, e.g. `2d`
+    #[arg(long, env, default_value = "2d", value_parser = parse_duration)]
+    dump_age_warning: Duration,
+    /// Refuse to use a database dump that was exported longer ago than this, e.g. `7d`
+    #[arg(long, env, value_parser = parse_duration)]
+    max_dump_age: Option<Duration>,
+    /// JSON or CSV file of supplementary crates, e.g. from a private registry, to
+    /// serve alongside the crates from the database dump. Can be passed multiple times
+    #[arg(long, env, value_delimiter = ',')]
+    supplementary_data: Vec<PathBuf>,
+    /// Comma-separated list of data to skip loading from the database dump, saving
+    /// memory and loading time when the template doesn't use it
+    #[arg(long, env, value_enum, value_delimiter = ',')]
+    skip_data: Vec<OptionalData>,
+    /// The number of threads to parse the database dump with.
+    /// Defaults to the number of CPUs
+    #[arg(long, env)]
+    load_threads: Option<usize>,
+    /// Print how long loading each table of the database dump took
+    #[arg(long, env)]
+    load_stats: bool,
+    /// The Jinja2 template to render images with. Either a path or an HTTP(S) URL.
+    /// Uses the bundled template if omitted
+    #[arg(short, long, env)]
+    template: Option<TemplateSource>,
+    /// Directory containing local assets, like logos or backgrounds, used by the template
+    #[arg(long, env)]
+    asset_dir: Option<PathBuf>,
+    /// Background image for the template, either a path or an HTTP(S) URL
+    #[arg(short, long, env)]
+    background: Option<String>,
+}
+
+impl CommonArgs {
+    /// The local path of the database dump
+    fn db_dump_path(&self) -> PathBuf {
+        self.db_dump_path.local_path()
+    }
+
+    /// Load the crates matching `filter` from the database dump and any
+    /// supplementary data files. When using the crates.io API as data
+    /// source, only supplementary crates are loaded up front.
+    async fn preload(&self, filter: LoadFilter) -> Result<CrateDb, Error> {
+        let mut supplementary = vec![];
+        for path in &self.supplementary_data {
+            let crates = supplement::load(path)?;
+            supplementary.extend(
+                crates
+                    .into_iter()
+                    .filter(|c| filter.matches(c.name.as_ref())),
+            );
+        }
+        let mut db = match self.source {
+            DataSource::Dump => {
+                let mut builder = CrateDb::builder(self.db_dump_path())
+                    .filter(filter)
+                    .options(self.load_options());
+                if let Some(threads) = self.load_threads {
+                    builder = builder.threads(threads);
+                }
+                let db = builder.load().await?;
+                self.check_dump_age(db.dump_timestamp())?;
+                db
+            }
+            DataSource::Api => CrateDb::empty(),
+        };
+        db.merge(supplementary);
+        Ok(db)
+    }
+
+    /// Which optional data to load, based on `--skip-data`
+    fn load_options(&self) -> LoadOptions {
+        let load = |data| !self.skip_data.contains(&data);
+        LoadOptions::default()
+            .keywords(load(OptionalData::Keywords))
+            .categories(load(OptionalData::Categories))
+            .dependents(load(OptionalData::Dependents))
+            .download_history(load(OptionalData::DownloadHistory))
+    }
+
+    /// Identifies the data [`Self::preload`] loads when loading every crate.
+    /// Changes when the database dump, supplementary data or options change.
+    fn data_key(&self) -> Result<u64, Error> {
+        let mut hasher = DefaultHasher::new();
+        augment::cache_key(&DbDump::open(self.db_dump_path()))?.hash(&mut hasher);
+        for path in &self.supplementary_data {
+            DbDump::open(path).fingerprint()?.hash(&mut hasher);
+        }
+        format!("{:?}", self.load_options()).hash(&mut hasher);
+        Ok(hasher.finish())
+    }
+
+    /// Warn if the dump `db` was loaded from is older than `--dump-age-warning`,
+    /// and return an error if it's older than `--max-dump-age`.
+    fn check_dump_age(&self, dump_timestamp: Option<DateTime<Utc>>) -> Result<(), Error> {
+        let Some(timestamp) = dump_timestamp else {
+            return Ok(());
+        };
+        let age = (Utc::now() - timestamp).to_std().unwrap_or_default();
+        let days = age.as_secs() / (24 * 60 * 60);
+        if self.max_dump_age.is_some_and(|max_age| age > max_age) {
+            return Err(anyhow::anyhow!(
+                "The database dump exported at {timestamp} is {days} days old, \
+                which exceeds --max-dump-age"
+            )
+            .into());
+        }
+        if age > self.dump_age_warning {
+            eprintln!("⚠️ The database dump exported at {timestamp} is {days} days old");
+        }
+        Ok(())
+    }
+
+    /// Download the database dump if it's configured to be
+    /// downloaded, and is missing or stale.
+    async fn ensure_db_dump(&self) -> Result<(), Error> {
+        let max_age = self.auto_download_max_age;
+        match &self.db_dump_path {
+            DbDumpLocation::Url(url) => {
+                let checksum = self.db_dump_sha256.as_ref();
+                download::ensure_db_dump(&self.db_dump_path(), url.as_str(), max_age, checksum)
+                    .await
+            }
+            DbDumpLocation::Path(path) if self.auto_download => {
+                let checksum = self.db_dump_sha256.as_ref();
+                download::ensure_db_dump(path, download::DB_DUMP_URL, max_age, checksum).await
+            }
+            DbDumpLocation::Path(_) => Ok(()),
+        }
+    }
+
+    /// Load the configured template, returning the
+    /// [`TemplateLoader`] so it can be reloaded later on.
+    async fn load_template(&self) -> Result<TemplateLoader, Error> {
+        let loader = TemplateLoader::new(self.template.clone().unwrap_or_default());
+        loader.load().await?;
+        Ok(loader)
+    }
+}
+
+/// Where crate data is taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DataSource {
+    /// The crates.io database dump
+    #[default]
+    Dump,
+    /// The crates.io API, fetching crates as they're requested. Avoids
+    /// downloading the database dump, but is only suitable for low volumes,
+    /// as the API is rate limited
+    Api,
+}
+
+/// Optional crate data that can be skipped when loading the database dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OptionalData {
+    Keywords,
+    Categories,
+    /// The number of dependents, which requires loading the largest table
+    Dependents,
+    /// The weekly and recent downloads
+    DownloadHistory,
+}
+
+/// Parse a duration consisting of a number and a unit, being one of
+/// `s`, `m`, `h` or `d`, e.g. `90s` or `2d`
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let unit_start = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("Duration '{s}' is missing a unit (s, m, h or d)"))?;
+    let (n, unit) = s.split_at(unit_start);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("Duration '{s}' must start with a number"))?;
+    let secs = match unit {
+        "s" => n,
+        "m" => n * 60,
+        "h" => n * 60 * 60,
+        "d" => n * 24 * 60 * 60,
+        _ => return Err(format!("Unknown duration unit '{unit}', use s, m, h or d")),
+    };
+    Ok(Duration::from_secs(secs))
 }
 
 #[derive(Debug, clap::Subcommand)]
+// Only ever parsed once, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum Command {
     /// Run the server
     Serve(Serve),
@@ -47,6 +300,8 @@ pub enum Command {
     OneShot(OneShot),
     /// Do a bulk conversion
     Bulk(Bulk),
+    /// Write the data that would be rendered to a JSON Lines or CSV file
+    Export(Export),
 }
 
 /// Set up a reusable HTTP client with a User Agent
@@ -62,3 +317,21 @@ pub static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
         .build()
         .unwrap()
 });
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use test_case::case;
+
+    #[case("90s" => Ok(Duration::from_secs(90)))]
+    #[case("15m" => Ok(Duration::from_secs(15 * 60)))]
+    #[case("12h" => Ok(Duration::from_secs(12 * 60 * 60)))]
+    #[case("2d" => Ok(Duration::from_secs(2 * 24 * 60 * 60)))]
+    #[case("2" => Err("Duration '2' is missing a unit (s, m, h or d)".to_string()))]
+    #[case("d" => Err("Duration 'd' must start with a number".to_string()))]
+    #[case("2w" => Err("Unknown duration unit 'w', use s, m, h or d".to_string()))]
+    fn test_parse_duration(s: &str) -> Result<Duration, String> {
+        super::parse_duration(s)
+    }
+}
@@ -1,8 +1,35 @@
-use clap::Parser;
+use std::process::ExitCode;
+
+use clap::{error::ErrorKind, Parser};
+use og_loc::{error::EXIT_INVALID_CRATE_NAME, spec::InvalidCrateName};
 
 #[tokio::main]
-async fn main() -> Result<(), og_loc::error::Error> {
+async fn main() -> ExitCode {
     dotenvy::dotenv().ok();
-    let cli = og_loc::Cli::parse();
-    cli.run().await
+    let cli = match og_loc::Cli::try_parse() {
+        Ok(cli) => cli,
+        // Clap exits with 2 on all usage errors, which is
+        // the exit code for crates that weren't found
+        Err(e) => {
+            let _ = e.print();
+            return match e.exit_code() {
+                // `--help` and `--version`
+                0 => ExitCode::SUCCESS,
+                _ if is_invalid_crate_name(&e) => EXIT_INVALID_CRATE_NAME.into(),
+                _ => ExitCode::FAILURE,
+            };
+        }
+    };
+    match cli.run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            e.exit_code()
+        }
+    }
+}
+
+fn is_invalid_crate_name(e: &clap::Error) -> bool {
+    e.kind() == ErrorKind::ValueValidation
+        && std::error::Error::source(e).is_some_and(|e| e.is::<InvalidCrateName>())
 }
@@ -0,0 +1,309 @@
+use std::{fmt, str::FromStr};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{CACHE_CONTROL, CONTENT_TYPE},
+    Url,
+};
+use sha2::{Digest, Sha256};
+use tokio::sync::Semaphore;
+
+use crate::{error::Error, HTTP_CLIENT};
+
+/// The location of a bucket, and the prefix to write objects under,
+/// e.g. `s3://og-images/crates` or `gs://og-images/crates`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketUrl {
+    provider: Provider,
+    bucket: String,
+    prefix: String,
+}
+
+/// The object storage providers [`BucketUrl`]s can point to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Provider {
+    /// Amazon S3, or any S3-compatible storage
+    S3,
+    /// Google Cloud Storage, through its S3-compatible XML API
+    Gcs,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid bucket URL '{0}', expected s3://bucket/prefix or gs://bucket/prefix")]
+pub struct InvalidBucketUrl(String);
+
+impl FromStr for BucketUrl {
+    type Err = InvalidBucketUrl;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidBucketUrl(s.to_string());
+        let (provider, rest) = if let Some(rest) = s.strip_prefix("s3://") {
+            (Provider::S3, rest)
+        } else if let Some(rest) = s.strip_prefix("gs://") {
+            (Provider::Gcs, rest)
+        } else {
+            return Err(invalid());
+        };
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            provider,
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+}
+
+impl fmt::Display for BucketUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.provider {
+            Provider::S3 => "s3",
+            Provider::Gcs => "gs",
+        };
+        write!(f, "{scheme}://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// Settings for uploading to a bucket, apart from the credentials
+#[derive(Debug, Clone)]
+pub struct BucketOptions {
+    /// The endpoint to send requests to, instead of the provider's default
+    pub endpoint: Option<Url>,
+    /// The region of the bucket. Defaults to `us-east-1` for S3
+    pub region: Option<String>,
+    /// The number of uploads to run at the same time
+    pub concurrency: usize,
+    /// The `Cache-Control` metadata to set on uploaded objects
+    pub cache_control: Option<String>,
+}
+
+/// Credentials for signing requests to a bucket
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// Uploads objects to a bucket using S3's API, with requests signed
+/// using AWS Signature Version 4. GCS buckets are used through
+/// their S3-compatible API, using HMAC keys as credentials.
+pub struct Bucket {
+    url: BucketUrl,
+    endpoint: Url,
+    region: String,
+    credentials: Credentials,
+    cache_control: Option<String>,
+    uploads: Semaphore,
+}
+
+impl Bucket {
+    /// Prepare to upload to the bucket at `url`, using the credentials in the
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, optionally,
+    /// `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env(url: BucketUrl, options: BucketOptions) -> Result<Self, Error> {
+        let var = |name| {
+            std::env::var(name)
+                .map_err(|_| anyhow::anyhow!("{name} must be set to upload to {url}"))
+        };
+        let credentials = Credentials {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        };
+        let region = options.region.unwrap_or_else(|| match url.provider {
+            Provider::S3 => "us-east-1".to_string(),
+            Provider::Gcs => "auto".to_string(),
+        });
+        let endpoint = match options.endpoint {
+            Some(endpoint) => endpoint,
+            None => match url.provider {
+                Provider::S3 => format!("https://s3.{region}.amazonaws.com").parse(),
+                Provider::Gcs => "https://storage.googleapis.com".parse(),
+            }
+            .map_err(anyhow::Error::from)?,
+        };
+        Ok(Self {
+            url,
+            endpoint,
+            region,
+            credentials,
+            cache_control: options.cache_control,
+            uploads: Semaphore::new(options.concurrency.max(1)),
+        })
+    }
+
+    /// Upload `contents` to the object called `name`, under the bucket's prefix
+    pub async fn put(&self, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+        let _permit = self.uploads.acquire().await.unwrap();
+        let key = match self.url.prefix.as_str() {
+            "" => name.to_string(),
+            prefix => format!("{prefix}/{name}"),
+        };
+        // Path-style URLs work for every provider, and
+        // for bucket names that aren't valid host names
+        let path = format!("/{}/{}", self.url.bucket, uri_encode(&key));
+        let mut request_url = self.endpoint.clone();
+        request_url.set_path(&path);
+        let host = match request_url.port() {
+            Some(port) => format!("{}:{port}", request_url.host_str().unwrap_or_default()),
+            None => request_url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let now = Utc::now();
+        let payload_hash = format!("{:x}", Sha256::digest(&contents));
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = self.authorization(now, &path, &headers, &payload_hash);
+
+        let mut request = HTTP_CLIENT
+            .put(request_url)
+            .header(CONTENT_TYPE, content_type(name))
+            .header("authorization", authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        if let Some(cache_control) = &self.cache_control {
+            request = request.header(CACHE_CONTROL, cache_control);
+        }
+        let response = request.body(contents).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Uploading {key} failed with {status}: {body}").into());
+        }
+        Ok(())
+    }
+
+    /// The `Authorization` header of a PUT request to `path`, signing `headers`
+    fn authorization(
+        &self,
+        now: chrono::DateTime<Utc>,
+        path: &str,
+        headers: &[(&str, String)],
+        payload_hash: &str,
+    ) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect();
+        let canonical_request =
+            format!("PUT\n{path}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}");
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{scope}\n{:x}",
+            now.format("%Y%m%dT%H%M%SZ"),
+            Sha256::digest(canonical_request)
+        );
+        let key = signing_key(
+            &self.credentials.secret_access_key,
+            &date,
+            &self.region,
+            "s3",
+        );
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.credentials.access_key_id
+        )
+    }
+}
+
+/// The key requests are signed with, derived from the secret access key
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    [date, region, service, "aws4_request"].into_iter().fold(
+        format!("AWS4{secret_access_key}").into_bytes(),
+        |key, part| hmac(&key, part.as_bytes()),
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Percent-encode everything in `key` but unreserved characters and slashes
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            b => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The `Content-Type` of the object called `name`, based on its extension
+fn content_type(name: &str) -> &'static str {
+    match name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("png") => "image/png",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::case;
+
+    use super::{BucketUrl, Provider};
+
+    #[case("s3://og-images/crates/" => Some((Provider::S3, "og-images".into(), "crates".into())))]
+    #[case("gs://og-images" => Some((Provider::Gcs, "og-images".into(), "".into())))]
+    #[case("s3:///crates" => None)]
+    #[case("./out" => None)]
+    fn test_parse_bucket_url(url: &str) -> Option<(Provider, String, String)> {
+        let url: BucketUrl = url.parse().ok()?;
+        Some((url.provider, url.bucket, url.prefix))
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // The example from AWS's documentation on deriving signing keys
+        let key = super::signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            super::hex(&key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[case("knien.png" => "knien.png")]
+    #[case("a b/c+d.png" => "a%20b/c%2Bd.png")]
+    fn test_uri_encode(key: &str) -> String {
+        super::uri_encode(key)
+    }
+
+    #[case("knien.png" => "image/png")]
+    #[case("index.html" => "text/html; charset=utf-8")]
+    #[case("knien.png.hash" => "application/octet-stream")]
+    fn test_content_type(name: &str) -> &'static str {
+        super::content_type(name)
+    }
+}
@@ -1,27 +1,387 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use tokio::io::AsyncWriteExt;
+use base64::{prelude::BASE64_STANDARD, Engine};
 
-use crate::{augment::CrateDb, error::Error, spec::CrateName, CommonArgs};
+use crate::{
+    augment::{CrateDb, LoadFilter},
+    bulk::write_output,
+    convert::{CrateData, OutputFormat, RenderOptions},
+    error::Error,
+    package,
+    source::CrateDataSource,
+    spec::VersionedCrateName,
+    template::TemplateSource,
+    CommonArgs,
+};
 
 #[derive(Debug, clap::Args)]
 pub struct OneShot {
-    /// The name of the crate
-    #[arg(env, long, short)]
-    pub name: CrateName,
-    /// The path to the PNG output file
+    /// The name of the crate, optionally followed by `@` and a version, e.g. `serde@1.0.100`,
+    /// to render that version instead of the default one. Can be given multiple times, or
+    /// as a comma-separated list, to render several crates from a single load of the database dump
+    #[arg(
+        env,
+        long,
+        short,
+        value_delimiter = ',',
+        required_unless_present_any = ["manifest_path", "data"]
+    )]
+    pub name: Vec<VersionedCrateName>,
+    /// Render the package in this `Cargo.toml` instead of a published crate, without
+    /// loading the database dump, to preview its card before publishing
+    #[arg(env, long, conflicts_with = "name")]
+    pub manifest_path: Option<PathBuf>,
+    /// Render the crate data in this JSON file as is, without loading the database
+    /// dump. The file has the same shape as the data passed to the template, which
+    /// is useful for mockups and template development
+    #[arg(env, long, conflicts_with_all = ["name", "manifest_path"])]
+    pub data: Option<PathBuf>,
+    /// Fetch the crate from the crates.io API instead of the database dump,
+    /// which then doesn't need to be downloaded. Shorthand for `--source api`
+    #[arg(env, long, conflicts_with_all = ["manifest_path", "data"])]
+    pub online: bool,
+    /// The path to the output file, or `-` to write the image to stdout. When rendering
+    /// multiple crates, the folder to write a `{name}.{format}` file, or `{name}@{version}.{format}`
+    /// if a version was requested, for each of them to. Defaults to `./{name}.{format}` for a single
+    /// crate, and the current directory for multiple crates
     #[arg(env, long = "out", short)]
-    pub out_path: PathBuf,
+    pub out_path: Option<PathBuf>,
+    /// The format of the image. Defaults to the format matching
+    /// the extension of `--out`, or PNG if there is none
+    #[arg(env, long, value_enum)]
+    pub format: Option<OutputFormat>,
+    /// How to encode the image when writing it to stdout with `--out -`. `base64` and `data-uri`
+    /// output can be embedded in HTML or JSON, and are followed by a newline
+    #[arg(env, long, value_enum, default_value = "raw")]
+    pub encoding: Encoding,
+    /// The Jinja2 template to render with, overriding the global `--template`.
+    /// Lets you iterate on a template without changing the configuration of `serve` or `bulk`
+    #[arg(
+        long = "template",
+        env = "ONE_SHOT_TEMPLATE",
+        conflicts_with = "print_data"
+    )]
+    pub template: Option<TemplateSource>,
+    /// Also write the Typst source rendered from the template, before it's compiled into
+    /// the image, to this path. When rendering multiple crates, the folder to write a
+    /// `{name}.typ` file for each of them to
+    #[arg(env, long, conflicts_with = "print_data")]
+    pub emit_typst: Option<PathBuf>,
+    /// Instead of writing an image, render the crate this many times after a warmup
+    /// render, and print how long each stage of rendering took
+    #[arg(env, long, value_name = "N", conflicts_with_all = ["print_data", "emit_typst", "out_path"])]
+    pub bench: Option<usize>,
+    /// Print the crate data passed to the template as JSON instead of
+    /// rendering it, to debug what ends up on the card
+    #[arg(env, long, conflicts_with_all = ["out_path", "format"])]
+    pub print_data: bool,
 }
 
 impl OneShot {
-    pub async fn run(self, common: CommonArgs) -> Result<(), Error> {
-        let db = CrateDb::preload_one(common.db_dump_path, self.name.inner().clone()).await?;
-        let data = db.augment_crate_spec(self.name)?;
-        let png = data.render_as_png().await;
-        let mut out_file = tokio::fs::File::create(self.out_path).await?;
-        out_file.write_all(&png).await?;
+    pub async fn run(self, mut common: CommonArgs) -> Result<(), Error> {
+        if let Some(template) = &self.template {
+            common.template = Some(template.clone());
+        }
+        if self.encoding != Encoding::Raw && self.out_path.as_deref() != Some(Path::new("-")) {
+            return Err(anyhow::anyhow!(
+                "--encoding only applies to images written to stdout with `--out -`"
+            )
+            .into());
+        }
+        if let Some(iterations) = self.bench {
+            common.load_template().await?;
+            return self.bench(&common, iterations).await;
+        }
+        let data = match (&self.data, &self.manifest_path) {
+            (Some(data), _) => read_crate_data(data).await?,
+            (None, Some(manifest_path)) => package::load_crate_data(manifest_path).await?,
+            (None, None) if self.print_data => return print_crates(&common, self.name).await,
+            (None, None) => {
+                let format = self.format();
+                common.load_template().await?;
+                let (out, emit_typst) = (self.out_path.as_deref(), self.emit_typst.as_deref());
+                return render_crates(&common, self.name, out, format, self.encoding, emit_typst)
+                    .await;
+            }
+        };
+        if self.print_data {
+            println!("{}", serde_json::to_string_pretty(&data).unwrap());
+            return Ok(());
+        }
+
+        let format = self.format();
+        let out_path = self
+            .out_path
+            .unwrap_or_else(|| default_out_path(&data.name, format));
+        common.load_template().await?;
+        if let Some(path) = &self.emit_typst {
+            write_typst_source(&data, path).await?;
+        }
+        let image = data.render_as(format, RenderOptions::default()).await?;
+        write_image(&out_path, &image, format, self.encoding).await?;
 
         Ok(())
     }
+
+    /// Render the crate `iterations` times after a warmup render, and print the
+    /// minimum, average and 95th percentile durations of each stage
+    async fn bench(&self, common: &CommonArgs, iterations: usize) -> Result<(), Error> {
+        let (label, db, data) = match (&self.data, &self.manifest_path, self.name.as_slice()) {
+            (Some(path), ..) => (
+                path.display().to_string(),
+                None,
+                Some(read_crate_data(path).await?),
+            ),
+            (None, Some(path), _) => (
+                path.display().to_string(),
+                None,
+                Some(package::load_crate_data(path).await?),
+            ),
+            (None, None, [name]) => {
+                let (db, _) = load_crates(common, vec![name.clone()]).await?;
+                (name.to_string(), Some((db, name)), None)
+            }
+            (None, None, _) => {
+                return Err(anyhow::anyhow!("--bench renders a single crate").into());
+            }
+        };
+
+        let mut augment = vec![];
+        let mut timings = vec![];
+        // The first render warms up caches, like those of fonts and avatars
+        for i in 0..=iterations {
+            let data = match (&data, &db) {
+                (Some(data), _) => data.clone(),
+                (None, Some((db, name))) => {
+                    let start = Instant::now();
+                    let data = crate_data(db, name).await?;
+                    if i > 0 {
+                        augment.push(start.elapsed());
+                    }
+                    data
+                }
+                (None, None) => unreachable!("either data or a db is loaded"),
+            };
+            let timed = tokio::task::spawn_blocking(move || {
+                data.render_png_timed(&RenderOptions::default())
+            })
+            .await
+            .unwrap()?;
+            if i > 0 {
+                timings.push(timed);
+            }
+        }
+
+        println!("⏱️ Rendered {label} {iterations} times, after a warmup render");
+        println!(
+            "  {:<10} {:>10} {:>10} {:>10}",
+            "stage", "min", "avg", "p95"
+        );
+        if !augment.is_empty() {
+            print_stage("augment", augment);
+        }
+        print_stage("template", timings.iter().map(|t| t.template).collect());
+        print_stage("compile", timings.iter().map(|t| t.compile).collect());
+        print_stage("rasterize", timings.iter().map(|t| t.rasterize).collect());
+        print_stage("encode", timings.iter().map(|t| t.encode).collect());
+
+        Ok(())
+    }
+
+    /// The format to render in: the one passed explicitly, or else
+    /// the one matching the extension of `--out`, or else PNG
+    fn format(&self) -> OutputFormat {
+        self.format
+            .or_else(|| self.out_path.as_deref().and_then(OutputFormat::from_path))
+            .unwrap_or_default()
+    }
+}
+
+/// Render the named crates from a single load of the database dump. A single
+/// crate is written to `out`, multiple crates to `{name}.{format}` files in the `out` folder.
+/// If `emit_typst` is set, the Typst source of each crate is written there as well.
+async fn render_crates(
+    common: &CommonArgs,
+    names: Vec<VersionedCrateName>,
+    out: Option<&Path>,
+    format: OutputFormat,
+    encoding: Encoding,
+    emit_typst: Option<&Path>,
+) -> Result<(), Error> {
+    let (db, names) = load_crates(common, names).await?;
+    if let [name] = names.as_slice() {
+        let data = crate_data(&db, name).await?;
+        if let Some(path) = emit_typst {
+            write_typst_source(&data, path).await?;
+        }
+        let image = data.render_as(format, RenderOptions::default()).await?;
+        let out = out.map_or_else(|| default_out_path(name, format), Path::to_path_buf);
+        return write_image(&out, &image, format, encoding).await;
+    }
+
+    let out = out.unwrap_or(Path::new("."));
+    if out == Path::new("-") {
+        return Err(anyhow::anyhow!("Only a single image can be written to stdout").into());
+    }
+    tokio::fs::create_dir_all(out).await?;
+    if let Some(emit_typst) = emit_typst {
+        tokio::fs::create_dir_all(emit_typst).await?;
+    }
+    let mut failed = 0;
+    let mut not_found = 0;
+    for name in &names {
+        let path = out.join(default_out_path(name, format));
+        let result = async {
+            let data = crate_data(&db, name).await?;
+            if let Some(emit_typst) = emit_typst {
+                write_typst_source(&data, &emit_typst.join(format!("{name}.typ"))).await?;
+            }
+            let image = data.render_as(format, RenderOptions::default()).await?;
+            write_output(&path, &image, true).await
+        }
+        .await;
+        match result {
+            Ok(()) => println!("🖼️ {name}: {}", path.display()),
+            Err(e) => {
+                eprintln!("❌ {name}: {e}");
+                failed += 1;
+                not_found += usize::from(matches!(e, Error::NotFound));
+            }
+        }
+    }
+    if failed > 0 && failed == not_found {
+        eprintln!("❌ {failed} of {} crates were not found", names.len());
+        return Err(Error::NotFound);
+    }
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{failed} of {} crates failed to render", names.len()).into());
+    }
+
+    Ok(())
+}
+
+/// How to encode images written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    /// The image as is
+    #[default]
+    Raw,
+    /// The image encoded as Base64
+    Base64,
+    /// A `data:` URI containing the image encoded as Base64
+    DataUri,
+}
+
+/// Write the image to `out`, or to stdout in the passed [`Encoding`] if `out` is `-`
+async fn write_image(
+    out: &Path,
+    image: &[u8],
+    format: OutputFormat,
+    encoding: Encoding,
+) -> Result<(), Error> {
+    if out != Path::new("-") {
+        return write_output(out, image, true).await;
+    }
+    let mut stdout = std::io::stdout().lock();
+    match encoding {
+        Encoding::Raw => stdout.write_all(image)?,
+        Encoding::Base64 => writeln!(stdout, "{}", BASE64_STANDARD.encode(image))?,
+        Encoding::DataUri => writeln!(
+            stdout,
+            "data:{};base64,{}",
+            format.mime_type(),
+            BASE64_STANDARD.encode(image)
+        )?,
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Print the minimum, average and 95th percentile of the durations of a stage
+fn print_stage(stage: &str, mut durations: Vec<Duration>) {
+    durations.sort_unstable();
+    let ms = |duration: Duration| format!("{:.2}ms", duration.as_secs_f64() * 1000.);
+    let min = durations.first().copied().unwrap_or_default();
+    let avg = durations.iter().sum::<Duration>() / (durations.len().max(1) as u32);
+    let index = ((durations.len().saturating_sub(1)) as f64 * 0.95).round() as usize;
+    let p95 = durations.get(index).copied().unwrap_or_default();
+    println!(
+        "  {stage:<10} {:>10} {:>10} {:>10}",
+        ms(min),
+        ms(avg),
+        ms(p95)
+    );
+}
+
+/// Write the Typst source rendered from the template for the crate to `path`
+async fn write_typst_source(data: &CrateData, path: &Path) -> Result<(), Error> {
+    let typ = data.typst_source(&RenderOptions::default())?;
+    write_output(path, typ.as_bytes(), true).await
+}
+
+/// The file name of the image of the crate called `name`, in the current directory
+fn default_out_path(name: &impl fmt::Display, format: OutputFormat) -> PathBuf {
+    format!("{name}.{}", format.extension()).into()
+}
+
+/// Print the data of the named crates as JSON, as a single object
+/// for a single crate, or an array for multiple crates
+async fn print_crates(common: &CommonArgs, names: Vec<VersionedCrateName>) -> Result<(), Error> {
+    let (db, names) = load_crates(common, names).await?;
+    let mut crates = vec![];
+    for name in names {
+        crates.push(crate_data(&db, &name).await?);
+    }
+    let json = match crates.as_slice() {
+        [data] => serde_json::to_string_pretty(data),
+        crates => serde_json::to_string_pretty(crates),
+    };
+    println!("{}", json.unwrap());
+
+    Ok(())
+}
+
+/// Load the named crates from the database dump with a single pass,
+/// returning them along with the names without duplicates
+async fn load_crates(
+    common: &CommonArgs,
+    names: Vec<VersionedCrateName>,
+) -> Result<(CrateDb, Vec<VersionedCrateName>), Error> {
+    let mut seen = HashSet::new();
+    let names: Vec<_> = names
+        .into_iter()
+        .filter(|name| seen.insert(name.clone()))
+        .collect();
+    let filter = match names.as_slice() {
+        [name] => LoadFilter::Single(name.name.inner().clone()),
+        names => LoadFilter::select(names.iter().map(|name| name.name.inner().clone())),
+    };
+    let db = common.preload(filter).await?;
+    Ok((db, names))
+}
+
+/// The data of the crate, using the version-specific data
+/// of the requested version if there is one
+async fn crate_data(db: &CrateDb, name: &VersionedCrateName) -> Result<CrateData, Error> {
+    match &name.version {
+        Some(version) => {
+            db.augment_crate_version_spec_or_fetch(name.name.clone(), version)
+                .await
+        }
+        None => db.crate_data(name.name.clone()).await,
+    }
+}
+
+/// Read a [`CrateData`] document from the JSON file at `path`
+async fn read_crate_data(path: &Path) -> Result<CrateData, Error> {
+    let contents = tokio::fs::read(path).await?;
+    let data = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow::anyhow!("Invalid crate data {}: {e}", path.display()))?;
+    Ok(data)
 }
@@ -0,0 +1,258 @@
+use std::path::Path;
+
+use chrono::Utc;
+
+use crate::{
+    augment::{truncate_description, MAX_KEYWORDS},
+    convert::{CrateCategory, CrateData, UserCrateOwner},
+    error::Error,
+};
+
+/// The parts of a `Cargo.toml` that are shown on a card
+#[derive(Debug, serde::Deserialize)]
+struct CargoToml {
+    package: Option<Package>,
+    workspace: Option<Workspace>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Package {
+    name: String,
+    description: Option<Inheritable<String>>,
+    version: Option<Inheritable<String>>,
+    authors: Option<Inheritable<Vec<String>>>,
+    license: Option<Inheritable<String>>,
+    #[serde(rename = "rust-version")]
+    rust_version: Option<Inheritable<String>>,
+    repository: Option<Inheritable<String>>,
+    homepage: Option<Inheritable<String>>,
+    documentation: Option<Inheritable<String>>,
+    keywords: Option<Inheritable<Vec<String>>>,
+    categories: Option<Inheritable<Vec<String>>>,
+}
+
+/// A package field, which may be inherited from the workspace,
+/// e.g. `license.workspace = true`
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum Inheritable<T> {
+    Value(T),
+    Workspace { workspace: bool },
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Workspace {
+    #[serde(default)]
+    package: WorkspacePackage,
+}
+
+/// The `[workspace.package]` table, with the values packages can inherit
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+struct WorkspacePackage {
+    description: Option<String>,
+    version: Option<String>,
+    authors: Option<Vec<String>>,
+    license: Option<String>,
+    #[serde(rename = "rust-version")]
+    rust_version: Option<String>,
+    repository: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    keywords: Option<Vec<String>>,
+    categories: Option<Vec<String>>,
+}
+
+/// Build [`CrateData`] from the package in the `Cargo.toml` at `path`, to preview
+/// its card before publishing. Fields inherited from the workspace are read from
+/// the nearest `Cargo.toml` with a `[workspace]` table. As the crate isn't
+/// published yet, it has no downloads or dependents, and its authors are
+/// shown as its owners, without avatars.
+pub async fn load_crate_data(path: &Path) -> Result<CrateData, Error> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let manifest = parse_cargo_toml(&contents, path)?;
+    let workspace = match manifest.workspace {
+        Some(workspace) => Some(workspace.package),
+        None => find_workspace(path).await?,
+    };
+    let package = manifest.package.ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} has no [package], is it a virtual manifest?",
+            path.display()
+        )
+    })?;
+    crate_data(package, workspace.unwrap_or_default())
+}
+
+fn parse_cargo_toml(contents: &str, path: &Path) -> Result<CargoToml, Error> {
+    Ok(toml::from_str(contents)
+        .map_err(|e| anyhow::anyhow!("Invalid manifest {}: {e}", path.display()))?)
+}
+
+/// The `[workspace.package]` of the workspace containing the package at `path`, if any
+async fn find_workspace(path: &Path) -> Result<Option<WorkspacePackage>, Error> {
+    let path = tokio::fs::canonicalize(path).await?;
+    for dir in path.ancestors().skip(2) {
+        let candidate = dir.join("Cargo.toml");
+        let contents = match tokio::fs::read_to_string(&candidate).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(workspace) = parse_cargo_toml(&contents, &candidate)?.workspace {
+            return Ok(Some(workspace.package));
+        }
+    }
+    Ok(None)
+}
+
+fn crate_data(package: Package, workspace: WorkspacePackage) -> Result<CrateData, Error> {
+    fn resolve<T>(
+        field: Option<Inheritable<T>>,
+        inherited: Option<T>,
+        name: &str,
+    ) -> Result<Option<T>, Error> {
+        match field {
+            None | Some(Inheritable::Workspace { workspace: false }) => Ok(None),
+            Some(Inheritable::Value(value)) => Ok(Some(value)),
+            Some(Inheritable::Workspace { workspace: true }) => match inherited {
+                Some(value) => Ok(Some(value)),
+                None => Err(anyhow::anyhow!(
+                    "`{name}` is inherited, but not set in [workspace.package]"
+                )
+                .into()),
+            },
+        }
+    }
+
+    let mut keywords =
+        resolve(package.keywords, workspace.keywords, "keywords")?.unwrap_or_default();
+    keywords.sort_unstable();
+    keywords.truncate(MAX_KEYWORDS);
+    let user_owners: Vec<_> = resolve(package.authors, workspace.authors, "authors")?
+        .unwrap_or_default()
+        .iter()
+        .map(|author| UserCrateOwner {
+            avatar: String::new(),
+            login: String::new(),
+            name: Some(author_name(author).to_string()),
+            url: String::new(),
+        })
+        .collect();
+    let num_owners = user_owners.len();
+    let mut categories: Vec<_> = resolve(package.categories, workspace.categories, "categories")?
+        .unwrap_or_default()
+        .into_iter()
+        // Display names are only known for published categories
+        .map(|slug| CrateCategory {
+            name: slug.clone(),
+            slug,
+        })
+        .collect();
+    categories.sort_unstable_by(|a, b| a.slug.cmp(&b.slug));
+    let now = Utc::now();
+
+    Ok(CrateData {
+        name: package.name.parse()?,
+        description: truncate_description(
+            resolve(package.description, workspace.description, "description")?.unwrap_or_default(),
+        ),
+        team_owners: vec![],
+        user_owners,
+        weekly_downloads: vec![],
+        license: resolve(package.license, workspace.license, "license")?,
+        version: resolve(package.version, workspace.version, "version")?,
+        rust_version: resolve(package.rust_version, workspace.rust_version, "rust-version")?,
+        crate_size: None,
+        published_at: None,
+        num_versions: 0,
+        num_owners,
+        downloads: 0,
+        recent_downloads: 0,
+        dependents: 0,
+        keywords,
+        categories,
+        repository: resolve(package.repository, workspace.repository, "repository")?,
+        homepage: resolve(package.homepage, workspace.homepage, "homepage")?,
+        documentation: resolve(
+            package.documentation,
+            workspace.documentation,
+            "documentation",
+        )?,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// The name of an author given as `Name <email>`
+fn author_name(author: &str) -> &str {
+    author
+        .split_once('<')
+        .map_or(author, |(name, _)| name)
+        .trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{crate_data, parse_cargo_toml};
+
+    #[test]
+    fn test_crate_data_from_cargo_toml() {
+        let manifest = parse_cargo_toml(
+            r#"
+            [package]
+            name = "og-loc"
+            version = "0.1.0"
+            description = "Open Graph image generator for crates.io"
+            authors = ["Jane Doe <jane@example.com>", "Mainmatter"]
+            license.workspace = true
+            keywords = ["open-graph", "image", "crates-io"]
+            categories = ["multimedia::images"]
+
+            [dependencies]
+            serde = "1"
+            "#,
+            Path::new("Cargo.toml"),
+        )
+        .unwrap();
+        let workspace = parse_cargo_toml(
+            r#"
+            [workspace]
+            members = ["og-loc"]
+
+            [workspace.package]
+            license = "MIT OR Apache-2.0"
+            "#,
+            Path::new("../Cargo.toml"),
+        )
+        .unwrap()
+        .workspace
+        .unwrap();
+
+        let data = crate_data(manifest.package.unwrap(), workspace.package).unwrap();
+        assert_eq!(data.name.as_ref(), "og-loc");
+        assert_eq!(data.version.as_deref(), Some("0.1.0"));
+        assert_eq!(data.license.as_deref(), Some("MIT OR Apache-2.0"));
+        assert_eq!(data.keywords, ["crates-io", "image", "open-graph"]);
+        let authors: Vec<_> = data
+            .user_owners
+            .iter()
+            .map(|owner| owner.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(authors, ["Jane Doe", "Mainmatter"]);
+        assert_eq!(data.num_owners, 2);
+        assert_eq!(data.categories[0].slug, "multimedia::images");
+        assert_eq!(data.repository, None);
+    }
+
+    #[test]
+    fn test_missing_workspace_value() {
+        let manifest = parse_cargo_toml(
+            "[package]\nname = \"og-loc\"\nlicense.workspace = true\n",
+            Path::new("Cargo.toml"),
+        )
+        .unwrap();
+        assert!(crate_data(manifest.package.unwrap(), Default::default()).is_err());
+    }
+}
@@ -1,46 +1,275 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
 
 use axum::{
-    body::Body,
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
     http::{
         header::{CONTENT_LENGTH, CONTENT_TYPE},
-        HeaderMap,
+        HeaderMap, StatusCode,
     },
     response::{IntoResponse, Redirect, Response},
     routing::get,
-    Router,
+    Json, Router,
 };
-use tokio::net::TcpListener;
+use chrono::{DateTime, Utc};
+use tokio::{net::TcpListener, sync::Semaphore};
 
-use crate::{augment::CrateDb, error::Error, spec::CrateNameOrPngFile, CommonArgs};
+use crate::{
+    augment::{normalize_crate_name, CrateDb, LoadFilter},
+    convert::{CrateData, RenderOptions},
+    crates_io,
+    dump::{DbDump, LOAD_PROGRESS},
+    error::Error,
+    index::CrateIndex,
+    parse_duration,
+    source::CrateDataSource,
+    spec::{CrateName, CrateNameOrPngFile},
+    template::TemplateLoader,
+    CommonArgs, DataSource,
+};
 
 const OG_IMAGE_FALLBACK_URL: &str = "https://crates.io/assets/og-image.png";
 
+/// The maximum total size of the rendered images kept in memory
+const IMAGE_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
 #[derive(Debug, clap::Args)]
 pub struct Serve {
     /// The socket address to listen on
     #[arg(env, long, short, default_value = "127.0.0.1:3000")]
     pub addr: SocketAddr,
+    /// Check for a new database dump at this interval, e.g. `6h`, and reload it
+    /// if it changed. Only the images of crates that changed are re-rendered
+    #[arg(env, long, value_parser = parse_duration)]
+    pub reload_interval: Option<Duration>,
+    /// Serve crate data from an on-disk index at this path instead of keeping every
+    /// crate in memory. The index is built from the database dump if it's missing or
+    /// out of date, which needs as much memory as loading the dump once
+    #[arg(env, long, conflicts_with = "reload_interval")]
+    pub index_path: Option<PathBuf>,
+    /// Refresh the data of frequently requested crates from the crates.io API
+    /// when the database dump is older than this, e.g. `1h`. Refreshed data
+    /// is reused for the same duration
+    #[arg(env, long, value_parser = parse_duration)]
+    pub api_refresh_ttl: Option<Duration>,
+}
+
+/// The number of requests within the refresh TTL that make a crate frequently requested
+const HOT_CRATE_REQUESTS: u32 = 3;
+
+/// The maximum number of crates refreshed from the crates.io API at once
+const MAX_REFRESHES: usize = 4;
+
+/// Refreshes the data of frequently requested crates from the crates.io API
+struct ApiRefresh {
+    ttl: Duration,
+    /// Limits the refreshes in flight to [`MAX_REFRESHES`]
+    slots: Arc<Semaphore>,
+    /// The number of recent requests per [normalized](normalize_crate_name) crate name
+    requests: moka::sync::Cache<String, Arc<AtomicU32>>,
+    /// Data fetched from the API, by [normalized](normalize_crate_name) crate name
+    fetched: moka::sync::Cache<String, CrateData>,
+}
+
+impl ApiRefresh {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            slots: Arc::new(Semaphore::new(MAX_REFRESHES)),
+            requests: moka::sync::Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(ttl)
+                .build(),
+            fetched: moka::sync::Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+}
+
+/// Where the server takes crate data from
+// Only ever stored once, behind an `Arc`, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
+enum CrateStore {
+    /// Every crate, loaded into memory
+    Db(CrateDb),
+    /// Every crate, in an on-disk index
+    Index(CrateIndex),
+}
+
+impl CrateStore {
+    fn dump_timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            CrateStore::Db(db) => db.dump_timestamp(),
+            CrateStore::Index(index) => index.dump_timestamp(),
+        }
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        match self {
+            CrateStore::Db(db) => db.contains(name),
+            CrateStore::Index(index) => matches!(index.contains(name), Ok(true)),
+        }
+    }
+}
+
+impl CrateDataSource for CrateStore {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        match self {
+            CrateStore::Db(db) => db.crate_data(name).await,
+            CrateStore::Index(index) => index.crate_data(name).await,
+        }
+    }
+}
+
+/// State shared between the request handlers
+struct AppState {
+    /// The loaded crate data, or `None` while it's still loading
+    db: RwLock<Option<Arc<CrateStore>>>,
+    /// Rendered images by [normalized](normalize_crate_name) crate name
+    images: moka::sync::Cache<String, Bytes>,
+    /// Refreshes frequently requested crates, if enabled
+    api_refresh: Option<ApiRefresh>,
+}
+
+impl AppState {
+    fn db(&self) -> Result<Arc<CrateStore>, Error> {
+        self.db.read().unwrap().clone().ok_or(Error::NotReady)
+    }
+
+    /// The data of the crate called `name`, preferring data refreshed from the API
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        let fetched = self
+            .api_refresh
+            .as_ref()
+            .and_then(|refresh| refresh.fetched.get(&normalize_crate_name(name.as_ref())));
+        match fetched {
+            Some(data) => Ok(data),
+            None => self.db()?.crate_data(name).await,
+        }
+    }
+
+    /// Count a request for the crate called `name`. If the crate is requested
+    /// frequently and the database dump is older than the refresh TTL, refresh
+    /// its data from the crates.io API in the background, invalidating its
+    /// cached image once the refreshed data is in. Only crates in the database
+    /// dump are refreshed, and at most [`MAX_REFRESHES`] at once.
+    fn record_request(self: &Arc<Self>, name: &CrateName) {
+        let Some(refresh) = &self.api_refresh else {
+            return;
+        };
+        let Ok(db) = self.db() else {
+            return;
+        };
+        let key = normalize_crate_name(name.as_ref());
+        if refresh.fetched.contains_key(&key) || !db.contains(name.as_ref()) {
+            return;
+        }
+        let requests = refresh.requests.get_with(key.clone(), Default::default);
+        if requests.fetch_add(1, Ordering::Relaxed) + 1 < HOT_CRATE_REQUESTS {
+            return;
+        }
+        let dump_age = db
+            .dump_timestamp()
+            .and_then(|timestamp| (Utc::now() - timestamp).to_std().ok());
+        if dump_age.is_some_and(|age| age < refresh.ttl) {
+            return;
+        }
+        // With too many refreshes in flight, try again on the next request
+        let Ok(slot) = refresh.slots.clone().try_acquire_owned() else {
+            return;
+        };
+        // Start counting anew, so the crate isn't refreshed again while
+        // this refresh is in progress, or right after it failed
+        refresh.requests.invalidate(&key);
+        let state = self.clone();
+        let name = name.clone();
+        tokio::spawn(async move {
+            let _slot = slot;
+            match crates_io::refresh_crate_data(name.clone()).await {
+                Ok(data) => {
+                    if let Some(refresh) = &state.api_refresh {
+                        refresh.fetched.insert(key.clone(), data);
+                    }
+                    state.images.invalidate(&key);
+                }
+                Err(e) => eprintln!("Error refreshing crate '{name}' from the crates.io API: {e}"),
+            }
+        });
+    }
 }
 
 impl Serve {
     /// Run the [`Serve`] subcommand. Sets up a simple HTTP server that
     /// listens on the configured socket address and exposes the Open
     /// Graph image generation funcationality under the `/og/{name}` and
-    /// GET endpoint.
+    /// GET endpoint. The data used to render the image is exposed as JSON
+    /// under the `/api/crates/{name}` GET endpoint. The server starts
+    /// listening right away, reporting its loading progress under the
+    /// `/readyz` GET endpoint until the database dump is loaded.
     pub async fn run(self, common: CommonArgs) -> Result<(), Error> {
-        let db = CrateDb::preload_all(common.db_dump_path).await?;
+        let template = common.load_template().await?;
+        let state = Arc::new(AppState {
+            db: RwLock::new(None),
+            images: moka::sync::Cache::builder()
+                .weigher(|_, png: &Bytes| png.len().try_into().unwrap_or(u32::MAX))
+                .max_capacity(IMAGE_CACHE_BYTES)
+                .build(),
+            api_refresh: self.api_refresh_ttl.map(ApiRefresh::new),
+        });
+        #[cfg(unix)]
+        tokio::spawn(reload_template_on_hangup(template, state.clone()));
+
+        /// Query parameters of the image endpoint
+        #[derive(serde::Deserialize)]
+        struct OgParams {
+            /// Respond with intermediate output instead of the image
+            debug: Option<OgDebug>,
+        }
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum OgDebug {
+            /// The Typst source rendered from the template
+            Typst,
+        }
+
         #[axum::debug_handler]
         async fn og(
             Path(spec): Path<CrateNameOrPngFile>,
-            State(db): State<Arc<CrateDb>>,
+            Query(params): Query<OgParams>,
+            State(state): State<Arc<AppState>>,
         ) -> Result<Response, Error> {
-            let Ok(data) = db.augment_crate_spec(spec.into()) else {
-                // If anything went wrong, just redirect to the fallback OG image
-                return Ok(Redirect::temporary(OG_IMAGE_FALLBACK_URL).into_response());
+            let name: CrateName = spec.into();
+            state.record_request(&name);
+            if let Some(OgDebug::Typst) = params.debug {
+                let data = state.crate_data(name).await?;
+                let typ = data.typst_source(&RenderOptions::default())?;
+                return Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], typ).into_response());
+            }
+            let key = normalize_crate_name(name.as_ref());
+            let png = match state.images.get(&key) {
+                Some(png) => png,
+                None => {
+                    let data = state.crate_data(name.clone()).await;
+                    let Ok(data) = data else {
+                        // If anything went wrong, just redirect to the fallback OG image
+                        return Ok(Redirect::temporary(OG_IMAGE_FALLBACK_URL).into_response());
+                    };
+                    let png = Bytes::from(data.render_as_png().await?);
+                    state.images.insert(key, png.clone());
+                    png
+                }
             };
-            let png = data.render_as_png().await;
 
             let mut headers = HeaderMap::new();
             headers.append(CONTENT_TYPE, "image/png".parse().unwrap());
@@ -50,15 +279,209 @@ impl Serve {
             Ok((headers, body).into_response())
         }
 
+        /// The augmented data of a crate, along with the
+        /// alternative text for its image.
+        #[derive(serde::Serialize)]
+        struct CrateDataResponse {
+            #[serde(flatten)]
+            data: CrateData,
+            alt: String,
+        }
+
+        #[axum::debug_handler]
+        async fn data(
+            Path(name): Path<CrateName>,
+            State(state): State<Arc<AppState>>,
+        ) -> Result<Json<CrateDataResponse>, Error> {
+            state.record_request(&name);
+            let data = state.crate_data(name).await?;
+            let alt = data.alt_text();
+            Ok(Json(CrateDataResponse { data, alt }))
+        }
+
+        /// Whether the server is ready to serve images, and if
+        /// not, how far along loading the database dump is.
+        #[derive(serde::Serialize)]
+        struct Readiness {
+            ready: bool,
+            progress: f64,
+            rows_loaded: u64,
+            /// When the loaded database dump was exported
+            dump_timestamp: Option<DateTime<Utc>>,
+        }
+
+        #[axum::debug_handler]
+        async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<Readiness>) {
+            if let Ok(db) = state.db() {
+                let readiness = Readiness {
+                    ready: true,
+                    progress: 1.,
+                    rows_loaded: LOAD_PROGRESS.rows(),
+                    dump_timestamp: db.dump_timestamp(),
+                };
+                return (StatusCode::OK, Json(readiness));
+            }
+            let readiness = Readiness {
+                ready: false,
+                progress: LOAD_PROGRESS.fraction(),
+                rows_loaded: LOAD_PROGRESS.rows(),
+                dump_timestamp: None,
+            };
+            (StatusCode::SERVICE_UNAVAILABLE, Json(readiness))
+        }
+
         let app = Router::new()
             .route("/og/{spec}", get(og))
             .route("/og/{spec}/", get(og))
-            .with_state(Arc::new(db));
+            .route("/api/crates/{name}", get(data))
+            .route("/readyz", get(readyz))
+            .with_state(state.clone());
 
         let listener = TcpListener::bind(self.addr).await?;
-
-        axum::serve(listener, app.into_make_service()).await?;
+        let server = async {
+            axum::serve(listener, app.into_make_service()).await?;
+            Ok(())
+        };
+        let load = async {
+            let db = match self.index_path {
+                Some(path) => CrateStore::Index(load_index(&common, path).await?),
+                None => CrateStore::Db(common.preload(LoadFilter::All).await?),
+            };
+            *state.db.write().unwrap() = Some(Arc::new(db));
+            println!("✅ Database dump loaded, ready to serve images");
+            let reload_interval = self
+                .reload_interval
+                .filter(|_| common.source == DataSource::Dump);
+            if let Some(interval) = reload_interval {
+                tokio::spawn(reload_db_periodically(
+                    common.clone(),
+                    interval,
+                    state.clone(),
+                ));
+            }
+            std::future::pending::<Result<(), Error>>().await
+        };
+        tokio::try_join!(server, load)?;
 
         Ok(())
     }
 }
+
+/// Open the index at `path`, building it from the crates [`CommonArgs::preload`]
+/// loads if it's missing or was built from different data.
+async fn load_index(common: &CommonArgs, path: PathBuf) -> Result<CrateIndex, Error> {
+    let key = common.data_key();
+    let existing = tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || CrateIndex::open(&path)
+    })
+    .await
+    .unwrap();
+    match (existing, key) {
+        (Ok(index), Ok(key)) if index.key() == key => {
+            println!("🗃️ Opened crate index at {}", path.display());
+            common.check_dump_age(index.dump_timestamp())?;
+            Ok(index)
+        }
+        // Without a database dump, an existing index is all there is
+        (Ok(index), Err(e)) => {
+            eprintln!(
+                "Could not check whether the index at {} is up to date: {e}",
+                path.display()
+            );
+            Ok(index)
+        }
+        (_, key) => {
+            let key = key?;
+            println!("🗃️ Building crate index at {}", path.display());
+            let db = common.preload(LoadFilter::All).await?;
+            tokio::task::spawn_blocking(move || CrateIndex::build(&db, &path, key))
+                .await
+                .unwrap()
+        }
+    }
+}
+
+/// Reload the template each time the process receives a `SIGHUP`.
+#[cfg(unix)]
+async fn reload_template_on_hangup(
+    template: TemplateLoader,
+    state: Arc<AppState>,
+) -> Result<(), Error> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        match template.load().await {
+            Ok(true) => {
+                // Every image may look different now
+                state.images.invalidate_all();
+                println!("🔄 Reloaded template from {}", template.source())
+            }
+            Ok(false) => println!("🔄 Template at {} is unchanged", template.source()),
+            Err(e) => eprintln!("Error reloading template from {}: {e}", template.source()),
+        }
+    }
+    Ok(())
+}
+
+/// Every `interval`, download a new database dump if configured to do so,
+/// and reload the [`CrateDb`] if the dump changed. Only the cached images
+/// of crates that were changed or removed are invalidated.
+async fn reload_db_periodically(common: CommonArgs, interval: Duration, state: Arc<AppState>) {
+    let fingerprint = |path| async move {
+        tokio::task::spawn_blocking(move || DbDump::open(path).fingerprint())
+            .await
+            .unwrap()
+    };
+    let mut last_fingerprint = fingerprint(common.db_dump_path()).await.ok();
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let result = async {
+            common.ensure_db_dump().await?;
+            let current_fingerprint = fingerprint(common.db_dump_path()).await?;
+            if last_fingerprint == Some(current_fingerprint) {
+                return Ok(None);
+            }
+            let db = common.preload(LoadFilter::All).await?;
+            last_fingerprint = Some(current_fingerprint);
+            Ok::<_, Error>(Some(db))
+        }
+        .await;
+
+        let db = match result {
+            Ok(Some(db)) => db,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Error reloading database dump: {e}");
+                continue;
+            }
+        };
+        let db = Arc::new(CrateStore::Db(db));
+        let Ok(old_db) = state.db() else {
+            continue;
+        };
+        let diff = tokio::task::spawn_blocking({
+            let db = db.clone();
+            move || match (&*old_db, &*db) {
+                (CrateStore::Db(old_db), CrateStore::Db(db)) => old_db.diff(db),
+                _ => unreachable!("Indexes are never reloaded"),
+            }
+        })
+        .await
+        .unwrap();
+        *state.db.write().unwrap() = Some(db);
+        for name in diff.changed.iter().chain(&diff.removed) {
+            state.images.invalidate(&normalize_crate_name(name));
+        }
+        println!(
+            "🔄 Reloaded database dump: {} crates added, {} changed, {} removed",
+            diff.added.len(),
+            diff.changed.len(),
+            diff.removed.len()
+        );
+    }
+}
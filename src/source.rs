@@ -0,0 +1,118 @@
+use std::{future::Future, sync::Arc};
+
+use crate::{augment::CrateDb, convert::CrateData, crates_io, error::Error, spec::CrateName};
+
+/// A source of [`CrateData`]. Implement this for alternative registries, like
+/// kellnr or Shipyard, to render images of their crates using OG Loc.
+pub trait CrateDataSource: Send + Sync {
+    /// The data of the crate called `name`, or [`Error::NotFound`]
+    /// if this source doesn't know about it.
+    fn crate_data(&self, name: CrateName) -> impl Future<Output = Result<CrateData, Error>> + Send;
+
+    /// Render the Open Graph image of the crate called `name` as PNG
+    fn render_png(&self, name: CrateName) -> impl Future<Output = Result<Vec<u8>, Error>> + Send {
+        async move { self.crate_data(name).await?.render_as_png().await }
+    }
+}
+
+/// Crates loaded from the database dump, and any supplementary data.
+/// Fetches missing crates from the crates.io API if enabled using
+/// [`enable_api_fallback`](crate::augment::enable_api_fallback).
+impl CrateDataSource for CrateDb {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        self.augment_crate_spec_or_fetch(name).await
+    }
+}
+
+/// Crates fetched from the crates.io API on demand
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CratesIoApi;
+
+impl CrateDataSource for CratesIoApi {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        crates_io::fetch_crate_data(name).await
+    }
+}
+
+impl<S: CrateDataSource> CrateDataSource for Arc<S> {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        (**self).crate_data(name).await
+    }
+}
+
+/// Tries the `primary` source first, and falls back to `secondary`
+/// for crates the `primary` source doesn't know about.
+#[derive(Debug, Clone)]
+pub struct Fallback<P, S> {
+    pub primary: P,
+    pub secondary: S,
+}
+
+impl<P: CrateDataSource, S: CrateDataSource> CrateDataSource for Fallback<P, S> {
+    async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+        match self.primary.crate_data(name.clone()).await {
+            Err(Error::NotFound) => self.secondary.crate_data(name).await,
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{CrateDataSource, Fallback};
+    use crate::{augment::CrateDb, convert::CrateData, error::Error, spec::CrateName};
+
+    /// A custom source serving crates from a map
+    struct StaticSource(HashMap<String, CrateData>);
+
+    impl CrateDataSource for StaticSource {
+        async fn crate_data(&self, name: CrateName) -> Result<CrateData, Error> {
+            self.0.get(name.as_ref()).cloned().ok_or(Error::NotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let data = CrateData {
+            name: "internal".parse().unwrap(),
+            description: "An internal crate".into(),
+            team_owners: vec![],
+            user_owners: vec![],
+            weekly_downloads: vec![],
+            license: None,
+            version: None,
+            rust_version: None,
+            crate_size: None,
+            published_at: None,
+            num_versions: 1,
+            num_owners: 0,
+            downloads: 0,
+            recent_downloads: 0,
+            dependents: 0,
+            keywords: vec![],
+            categories: vec![],
+            repository: None,
+            homepage: None,
+            documentation: None,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+        };
+        let source = Fallback {
+            primary: CrateDb::empty(),
+            secondary: StaticSource(HashMap::from([("internal".into(), data.clone())])),
+        };
+        assert_eq!(
+            source
+                .crate_data("internal".parse().unwrap())
+                .await
+                .unwrap(),
+            data
+        );
+        assert!(matches!(
+            source.crate_data("missing".parse().unwrap()).await,
+            Err(Error::NotFound)
+        ));
+    }
+}
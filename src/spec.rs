@@ -32,7 +32,7 @@ impl TryFrom<&str> for CrateNameOrPngFile {
 
 /// A valid crate name.
 #[derive(Clone, Debug, Hash, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
-#[serde(try_from = "&str")]
+#[serde(try_from = "String")]
 pub struct CrateName(String);
 
 impl CrateName {
@@ -86,6 +86,14 @@ impl TryFrom<&str> for CrateName {
     }
 }
 
+impl TryFrom<String> for CrateName {
+    type Error = <CrateName as FromStr>::Err;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
 impl FromStr for CrateName {
     type Err = InvalidCrateName;
 
@@ -119,6 +127,42 @@ impl FromStr for CrateName {
     }
 }
 
+/// A crate name, optionally followed by `@` and a version, e.g. `serde@1.0.100`
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct VersionedCrateName {
+    pub name: CrateName,
+    pub version: Option<String>,
+}
+
+impl FromStr for VersionedCrateName {
+    type Err = InvalidCrateName;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let Some((name, version)) = spec.split_once('@') else {
+            return Ok(Self {
+                name: spec.parse()?,
+                version: None,
+            });
+        };
+        if version.is_empty() {
+            return InvalidCrateName::err_with_msg("Versions after `@` cannot be empty");
+        }
+        Ok(Self {
+            name: name.parse()?,
+            version: Some(version.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for VersionedCrateName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}@{version}", self.name),
+            None => self.name.fmt(f),
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(try_from = "&str")]
 pub struct CratePngFile(CrateName);
@@ -159,7 +203,7 @@ impl TryFrom<&str> for CratePngFile {
 mod tests {
     use test_case::case;
 
-    use super::{CrateName, InvalidCrateName};
+    use super::{CrateName, InvalidCrateName, VersionedCrateName};
 
     #[case("" => InvalidCrateName::err_with_msg("Crate names cannot be empty"))]
     #[case("aksajdkajhdskjashdkjahdkajshdkajshdklajhdlkjashdkjadkjadkashdakdkajshda" => InvalidCrateName::err_with_msg("Crate names can not be longer than 64 characters"))]
@@ -171,4 +215,15 @@ mod tests {
     fn test_crate_name_validation(name: &str) -> Result<CrateName, InvalidCrateName> {
         name.parse()
     }
+
+    #[case("serde" => Ok((CrateName("serde".to_string()), None)))]
+    #[case("serde@1.0.100" => Ok((CrateName("serde".to_string()), Some("1.0.100".to_string()))))]
+    #[case("serde@" => InvalidCrateName::err_with_msg("Versions after `@` cannot be empty"))]
+    #[case("@1.0.0" => InvalidCrateName::err_with_msg("Crate names cannot be empty"))]
+    fn test_versioned_crate_name(
+        spec: &str,
+    ) -> Result<(CrateName, Option<String>), InvalidCrateName> {
+        let VersionedCrateName { name, version } = spec.parse()?;
+        Ok((name, version))
+    }
 }
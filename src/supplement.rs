@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{error::Error, spec::CrateName};
+
+/// A crate from a supplementary data file, e.g. one published to a private
+/// registry, to be [merged](crate::augment::CrateDb::merge) with the
+/// crates from the database dump.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SupplementaryCrate {
+    pub name: CrateName,
+    #[serde(default)]
+    pub description: String,
+    /// The GitHub logins of the crate's owners
+    #[serde(default, deserialize_with = "list")]
+    pub owners: Vec<String>,
+    /// The version number of the crate's default version
+    pub version: Option<String>,
+    pub license: Option<String>,
+    #[serde(default)]
+    pub downloads: u64,
+    #[serde(default)]
+    pub recent_downloads: u64,
+    #[serde(default, deserialize_with = "list")]
+    pub keywords: Vec<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Deserialize either a sequence of strings, or a single comma-separated
+/// string, as CSV files can't contain sequences.
+fn list<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Many(Vec<String>),
+        One(String),
+    }
+
+    Ok(match List::deserialize(deserializer)? {
+        List::Many(items) => items,
+        List::One(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect(),
+    })
+}
+
+/// Load the crates in the supplementary data file at `path`. Either a `.json`
+/// file containing an array of crates, or a `.csv` file with a header row
+/// naming the fields of [`SupplementaryCrate`]. In CSV files, owners and
+/// keywords are comma-separated.
+pub fn load(path: &Path) -> Result<Vec<SupplementaryCrate>, Error> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => {
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            serde_json::from_reader(file).map_err(|e| {
+                anyhow::anyhow!("Invalid supplementary data in {}: {e}", path.display()).into()
+            })
+        }
+        Some("csv") => Ok(csv::Reader::from_path(path)?
+            .into_deserialize()
+            .collect::<Result<_, _>>()?),
+        _ => Err(anyhow::anyhow!(
+            "Supplementary data must be a .json or .csv file, got {}",
+            path.display()
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SupplementaryCrate;
+
+    #[test]
+    fn test_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("crates.json");
+        std::fs::write(
+            &json_path,
+            r#"[{"name":"internal-auth","owners":["alice","bob"],"keywords":["auth"],"downloads":12}]"#,
+        )
+        .unwrap();
+        let csv_path = dir.path().join("crates.csv");
+        std::fs::write(
+            &csv_path,
+            "name,description,owners,keywords,downloads\n\
+            internal-auth,,\"alice, bob\",auth,12\n",
+        )
+        .unwrap();
+
+        let from_json = super::load(&json_path).unwrap();
+        let from_csv = super::load(&csv_path).unwrap();
+        assert_eq!(from_json, from_csv);
+        let [SupplementaryCrate {
+            name,
+            owners,
+            downloads,
+            ..
+        }] = &from_json[..]
+        else {
+            panic!("Expected a single crate, got {from_json:?}");
+        };
+        assert_eq!(name.as_ref(), "internal-auth");
+        assert_eq!(owners, &["alice", "bob"]);
+        assert_eq!(*downloads, 12);
+
+        assert!(super::load(&dir.path().join("crates.txt")).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    sync::{LazyLock, Mutex, RwLock},
+};
+
+use aho_corasick::AhoCorasick;
+use chrono::{DateTime, Utc};
+use minijinja::{Environment, ErrorKind, State};
+use reqwest::{
+    header::{ETAG, IF_NONE_MATCH},
+    StatusCode, Url,
+};
+
+use crate::{convert, error::Error, HTTP_CLIENT};
+
+/// Identifier for the Open Graph template in the
+/// [`minijinja::Environment`]
+const OG_TEMPLATE_NAME: &str = "og-typst";
+
+/// The template that is compiled into the binary, used
+/// if no other template was configured.
+const BUNDLED_TEMPLATE_J2: &str = include_str!("../template.typ.j2");
+
+/// The [`minijinja::Environment`] for rendering the
+/// Jinja2 template to Typst source. Starts out with the
+/// bundled template, which can be swapped using [`set_template`].
+static TEMPLATE_ENV: LazyLock<RwLock<Environment<'static>>> = LazyLock::new(|| {
+    let env = build_env(BUNDLED_TEMPLATE_J2.into()).expect("Bundled template is invalid");
+    RwLock::new(env)
+});
+
+fn build_env(source: Cow<'static, str>) -> Result<Environment<'static>, minijinja::Error> {
+    let mut env = Environment::new();
+    register_filters(&mut env);
+    env.add_template_owned(OG_TEMPLATE_NAME, source)?;
+    Ok(env)
+}
+
+/// Replace the template used for rendering. The template is
+/// compiled up front, so a template with syntax errors
+/// will be rejected and the current one is kept.
+pub fn set_template(source: String) -> Result<(), minijinja::Error> {
+    let env = build_env(source.into())?;
+    *TEMPLATE_ENV.write().unwrap() = env;
+    Ok(())
+}
+
+/// Render the current template with the passed context.
+pub fn render(ctx: minijinja::Value) -> Result<String, minijinja::Error> {
+    render_with(&TEMPLATE_ENV.read().unwrap(), ctx)
+}
+
+/// Render the template in `env` with the passed context.
+pub(crate) fn render_with(
+    env: &Environment<'_>,
+    ctx: minijinja::Value,
+) -> Result<String, minijinja::Error> {
+    env.get_template(OG_TEMPLATE_NAME)?.render(ctx)
+}
+
+/// The source of the current template, e.g. to detect changes to it.
+pub fn template_source() -> String {
+    TEMPLATE_ENV
+        .read()
+        .unwrap()
+        .get_template(OG_TEMPLATE_NAME)
+        .expect("The template is always present")
+        .source()
+        .to_string()
+}
+
+/// Where to load the Jinja2 template from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TemplateSource {
+    /// The template bundled with the binary
+    #[default]
+    Bundled,
+    /// A template on the local file system
+    Path(PathBuf),
+    /// A template served over HTTP(S)
+    Url(Url),
+}
+
+impl FromStr for TemplateSource {
+    type Err = url::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            return Ok(Self::Url(s.parse()?));
+        }
+        Ok(Self::Path(s.into()))
+    }
+}
+
+impl fmt::Display for TemplateSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateSource::Bundled => "<bundled>".fmt(f),
+            TemplateSource::Path(path) => path.display().fmt(f),
+            TemplateSource::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+/// Loads a template from a [`TemplateSource`] and installs it. Can be
+/// used to reload the template later on. Templates other than the
+/// bundled one are first tried out on a sample crate, so a broken
+/// template is rejected and the current one is kept. Remote templates
+/// are revalidated using their `ETag`, so unchanged templates are not
+/// downloaded again.
+#[derive(Debug)]
+pub struct TemplateLoader {
+    source: TemplateSource,
+    etag: Mutex<Option<String>>,
+}
+
+impl TemplateLoader {
+    pub fn new(source: TemplateSource) -> Self {
+        Self {
+            source,
+            etag: Mutex::new(None),
+        }
+    }
+
+    pub fn source(&self) -> &TemplateSource {
+        &self.source
+    }
+
+    /// (Re)load the template. Returns whether the template was changed.
+    pub async fn load(&self) -> Result<bool, Error> {
+        let (source, etag) = match &self.source {
+            TemplateSource::Bundled => {
+                // The bundled template is known to work
+                set_template(BUNDLED_TEMPLATE_J2.into())?;
+                return Ok(true);
+            }
+            TemplateSource::Path(path) => (tokio::fs::read_to_string(path).await?, None),
+            TemplateSource::Url(url) => match self.fetch(url.clone()).await? {
+                Some(fetched) => fetched,
+                None => return Ok(false),
+            },
+        };
+        let env = build_env(source.into())?;
+        // Compiling the template takes a while
+        let env = tokio::task::spawn_blocking(move || convert::trial_render(&env).map(|()| env))
+            .await
+            .unwrap()?;
+        *TEMPLATE_ENV.write().unwrap() = env;
+        // Only now, so a broken template is fetched again after it was fixed
+        *self.etag.lock().unwrap() = etag;
+        Ok(true)
+    }
+
+    /// Fetch a remote template along with its `ETag`, returning
+    /// `None` if it was not modified since the last fetch.
+    async fn fetch(&self, url: Url) -> Result<Option<(String, Option<String>)>, Error> {
+        let mut request = HTTP_CLIENT.get(url);
+        let etag = self.etag.lock().unwrap().clone();
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        let source = response.text().await?;
+        Ok(Some((source, etag)))
+    }
+}
+
+/// Register the custom filters used by the Jinja2 templates
+/// in the passed [`minijinja::Environment`].
+pub fn register_filters(env: &mut Environment) {
+    env.add_filter("typst_escape", typst_escape);
+    env.add_filter("humanize_number", humanize_number);
+    env.add_filter("reltime", reltime);
+}
+
+/// Escape a string so that it is safe to use in a typst string literal.
+/// Only backslashes and double quotes have a special meaning in there.
+pub fn typst_escape(s: &str) -> String {
+    const NUM_REPLACE_ITEMS: usize = 2;
+    const REPLACE: [(&str, &str); NUM_REPLACE_ITEMS] = [(r"\", r"\\"), (r#"""#, r#"\""#)];
+
+    // Alas, <[N;T]>::map is not const, so instead we have to do this
+    static PATTERNS: LazyLock<[&str; NUM_REPLACE_ITEMS]> =
+        LazyLock::new(|| REPLACE.map(|(p, _)| p));
+    static ESCAPED: LazyLock<[&str; NUM_REPLACE_ITEMS]> = LazyLock::new(|| REPLACE.map(|(_, e)| e));
+    static MATCHER: LazyLock<AhoCorasick> =
+        LazyLock::new(|| AhoCorasick::new(PATTERNS.iter()).expect("Error setting up matcher"));
+
+    MATCHER.replace_all(s, &*ESCAPED)
+}
+
+/// Format a number in a short, human-friendly way,
+/// e.g. `1234567` becomes `1.2M`.
+pub fn humanize_number(n: u64) -> String {
+    const UNITS: [(u64, &str); 3] = [(1_000_000_000, "B"), (1_000_000, "M"), (1_000, "K")];
+
+    let Some((scale, unit)) = UNITS.into_iter().find(|(scale, _)| n >= *scale) else {
+        return n.to_string();
+    };
+    // Round down to a single decimal, so that we never overstate
+    let tenths = n / (scale / 10);
+    match tenths % 10 {
+        0 => format!("{}{unit}", tenths / 10),
+        d => format!("{}.{d}{unit}", tenths / 10),
+    }
+}
+
+/// Format an RFC 3339 timestamp relative to the current time,
+/// e.g. `3 days ago`. The current time is taken from the `now`
+/// variable if it's set, so that renders can be made reproducible.
+fn reltime(state: &State, timestamp: &str) -> Result<String, minijinja::Error> {
+    let parse = |timestamp: &str| {
+        DateTime::parse_from_rfc3339(timestamp)
+            .map(|t| t.to_utc())
+            .map_err(|e| {
+                minijinja::Error::new(ErrorKind::InvalidOperation, "invalid timestamp")
+                    .with_source(e)
+            })
+    };
+    let now = match state.lookup("now") {
+        Some(now) => parse(now.as_str().unwrap_or_default())?,
+        None => Utc::now(),
+    };
+    Ok(reltime_at(parse(timestamp)?, now))
+}
+
+fn reltime_at(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    const UNITS: [(i64, &str); 6] = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    let secs = (now - timestamp).num_seconds();
+    if secs < 0 {
+        return "in the future".to_string();
+    }
+    match UNITS.into_iter().find(|(unit_secs, _)| secs >= *unit_secs) {
+        Some((unit_secs, unit)) => {
+            let n = secs / unit_secs;
+            let s = if n == 1 { "" } else { "s" };
+            format!("{n} {unit}{s} ago")
+        }
+        None => "just now".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use test_case::case;
+
+    use super::TemplateSource;
+
+    #[case("https://example.com/card.typ.j2" => TemplateSource::Url("https://example.com/card.typ.j2".parse().unwrap()))]
+    #[case("./card.typ.j2" => TemplateSource::Path("./card.typ.j2".into()))]
+    fn test_template_source(s: &str) -> TemplateSource {
+        s.parse().unwrap()
+    }
+
+    #[case(r#"Say "hi" to #tag, $5 ^ \n"# => r#"Say \"hi\" to #tag, $5 ^ \\n"#)]
+    #[case("Nothing to escape" => "Nothing to escape")]
+    fn test_typst_escape(s: &str) -> String {
+        super::typst_escape(s)
+    }
+
+    #[case(crate::template::BUNDLED_TEMPLATE_J2 => true)]
+    // Compiles to Typst that doesn't compile
+    #[case("#undefined_function()" => false)]
+    // Fails to render, as the filter only takes numbers
+    #[case("{{ krate.name | humanize_number }}" => false)]
+    fn test_trial_render(source: &str) -> bool {
+        let env = super::build_env(source.to_string().into()).unwrap();
+        crate::convert::trial_render(&env).is_ok()
+    }
+
+    #[case(0 => "0")]
+    #[case(999 => "999")]
+    #[case(1_000 => "1K")]
+    #[case(1_299 => "1.2K")]
+    #[case(1_234_567 => "1.2M")]
+    #[case(999_999_999 => "999.9M")]
+    #[case(12_000_000_000 => "12B")]
+    fn test_humanize_number(n: u64) -> String {
+        super::humanize_number(n)
+    }
+
+    #[case("2025-01-01T11:59:30Z" => "just now")]
+    #[case("2025-01-01T11:00:00Z" => "1 hour ago")]
+    #[case("2024-12-29T12:00:00Z" => "3 days ago")]
+    #[case("2023-01-01T12:00:00Z" => "2 years ago")]
+    #[case("2025-01-02T12:00:00Z" => "in the future")]
+    fn test_reltime(timestamp: &str) -> String {
+        let now: DateTime<Utc> = "2025-01-01T12:00:00Z".parse().unwrap();
+        super::reltime_at(timestamp.parse().unwrap(), now)
+    }
+}